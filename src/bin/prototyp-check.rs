@@ -0,0 +1,344 @@
+// Loads every map, prototype and dialogue without opening a window
+// and reports broken references between them.
+//
+// Run from the repository root: `cargo run --bin prototyp-check`
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use prototyp::{
+    dialogue, map_classes,
+    prototypes::{extends, overrides, validate},
+    tmx,
+};
+use serde::Deserialize;
+use tiled::PropertyValue;
+
+const ASSETS_DIR: &str = "assets";
+const PROTOTYPES_DIR: &str = "assets/prototypes";
+const DIALOGUES_DIR: &str = "assets/dialogues";
+
+#[derive(Deserialize)]
+struct PrototypeFile {
+    name: String,
+    #[serde(default)]
+    components: Vec<PrototypeComponent>,
+}
+
+#[derive(Deserialize)]
+struct PrototypeComponent {
+    #[serde(rename = "type")]
+    component_type: String,
+    #[serde(default)]
+    value: serde_yaml::Value,
+}
+
+struct Prototype {
+    path: PathBuf,
+    npc_id: Option<u64>,
+    talks: bool,
+    // With its `extends` resolved.
+    data: serde_yaml::Value,
+}
+
+#[derive(Default)]
+struct Report {
+    problems: Vec<String>,
+}
+
+impl Report {
+    fn error(&mut self, path: &Path, message: impl AsRef<str>) {
+        self.problems
+            .push(format!("{}: {}", path.display(), message.as_ref()));
+    }
+}
+
+fn files_with_extension(dir: &Path, extension: &str, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            files_with_extension(&path, extension, files);
+        } else if path.extension().map_or(false, |ext| ext == extension) {
+            files.push(path);
+        }
+    }
+}
+
+fn load_prototypes(report: &mut Report) -> HashMap<String, Prototype> {
+    let mut files = Vec::new();
+    files_with_extension(Path::new(PROTOTYPES_DIR), "yaml", &mut files);
+    files.sort();
+
+    let mut prototypes: HashMap<String, Prototype> = HashMap::new();
+    for path in files {
+        let loaded = std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|s| extends::resolve(&s, Path::new(PROTOTYPES_DIR)))
+            .and_then(|data| {
+                let file: PrototypeFile =
+                    serde_yaml::from_value(data.clone()).map_err(|e| e.to_string())?;
+                Ok((file, data))
+            });
+        let (file, data) = match loaded {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                report.error(&path, format!("Could not load prototype: {e}"));
+                continue;
+            }
+        };
+
+        let mut prototype = Prototype {
+            path: path.clone(),
+            npc_id: None,
+            talks: false,
+            data,
+        };
+
+        for component in file.components.iter() {
+            match component.component_type.as_str() {
+                "NPC" => match component.value.as_u64() {
+                    Some(id) => prototype.npc_id = Some(id),
+                    None => report.error(&path, "NPC component without a numeric id"),
+                },
+                "AI" => {
+                    prototype.talks = component.value.get("kind").and_then(|kind| kind.as_str())
+                        == Some("Talking");
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(other) = prototypes.get(&file.name) {
+            report.error(
+                &path,
+                format!(
+                    "Prototype {} is also defined in {}",
+                    file.name,
+                    other.path.display()
+                ),
+            );
+            continue;
+        }
+
+        prototypes.insert(file.name, prototype);
+    }
+
+    prototypes
+}
+
+fn check_prototypes(prototypes: &HashMap<String, Prototype>, report: &mut Report) {
+    let prototype_names: HashSet<String> = prototypes.keys().cloned().collect();
+    let mut names: Vec<_> = prototypes.keys().collect();
+    names.sort();
+
+    let mut npc_ids: HashMap<u64, &str> = HashMap::new();
+    for name in names {
+        let prototype = &prototypes[name];
+        for problem in validate::validate(&prototype.data, &prototype_names, Path::new(ASSETS_DIR))
+        {
+            report.error(&prototype.path, problem.to_string());
+        }
+
+        let Some(npc_id) = prototype.npc_id else {
+            continue;
+        };
+
+        if let Some(other) = npc_ids.insert(npc_id, name) {
+            report.error(
+                &prototype.path,
+                format!("NPC id {npc_id} is also used by prototype {other}"),
+            );
+        }
+
+        // Only talking NPCs load their dialogue.
+        let dialogue = Path::new(DIALOGUES_DIR).join(format!("{npc_id}.diag"));
+        if prototype.talks && !dialogue.exists() {
+            report.error(
+                &prototype.path,
+                format!(
+                    "NPC {npc_id} talks, but {} doesn't exist",
+                    dialogue.display()
+                ),
+            );
+        }
+    }
+}
+
+fn check_dialogues(report: &mut Report) {
+    let mut files = Vec::new();
+    files_with_extension(Path::new(DIALOGUES_DIR), "diag", &mut files);
+    files.sort();
+
+    for path in files {
+        if let Err(e) = dialogue::Dialogue::load(&path) {
+            report.error(&path, e);
+        }
+    }
+}
+
+fn check_image(map_path: &Path, image: &tiled::Image, report: &mut Report) {
+    if !image.source.exists() {
+        report.error(
+            map_path,
+            format!("Missing tileset image {}", image.source.display()),
+        );
+    }
+}
+
+// Checks the prototype as overridden by the properties of an NPC object.
+fn check_overrides(
+    map_path: &Path,
+    describe: &str,
+    prototype: &Prototype,
+    properties: &tiled::Properties,
+    prototypes: &HashMap<String, Prototype>,
+    report: &mut Report,
+) {
+    if let Some(PropertyValue::StringValue(file)) = properties.get(overrides::DIALOGUE) {
+        let dialogue = Path::new(DIALOGUES_DIR).join(file);
+        if !dialogue.exists() {
+            report.error(
+                map_path,
+                format!("{describe} has a missing dialogue {}", dialogue.display()),
+            );
+        }
+    }
+
+    let components = match overrides::component_overrides(&prototype.data, properties) {
+        Ok(components) => components,
+        Err(e) => {
+            report.error(map_path, format!("{describe}: {e}"));
+            return;
+        }
+    };
+    if components.is_empty() {
+        return;
+    }
+
+    let names: HashSet<String> = prototypes.keys().cloned().collect();
+    let overridden = overrides::apply_overrides(&prototype.data, &components);
+    for problem in validate::validate(&overridden, &names, Path::new(ASSETS_DIR)) {
+        report.error(map_path, format!("{describe} overrides {problem}"));
+    }
+}
+
+fn check_objects<'map>(
+    map_path: &Path,
+    layers: impl Iterator<Item = tiled::Layer<'map>>,
+    prototypes: &HashMap<String, Prototype>,
+    report: &mut Report,
+) {
+    for layer in layers {
+        let obj_layer = match layer.layer_type() {
+            tiled::LayerType::ObjectLayer(obj_layer) => obj_layer,
+            tiled::LayerType::GroupLayer(group) => {
+                check_objects(map_path, group.layers(), prototypes, report);
+                continue;
+            }
+            _ => continue,
+        };
+
+        for object in obj_layer.objects() {
+            let describe = || {
+                format!(
+                    "{} object {} on layer {}",
+                    object.user_type,
+                    object.id(),
+                    layer.name
+                )
+            };
+
+            if object.user_type == map_classes::NPC {
+                match object.properties.get("id") {
+                    Some(PropertyValue::StringValue(id)) if !id.is_empty() => {
+                        match prototypes.get(id) {
+                            Some(prototype) => check_overrides(
+                                map_path,
+                                &describe(),
+                                prototype,
+                                &object.properties,
+                                prototypes,
+                                report,
+                            ),
+                            None => report.error(
+                                map_path,
+                                format!("{} refers to a missing prototype {id}", describe()),
+                            ),
+                        }
+                    }
+                    _ => report.error(map_path, format!("{} has no id", describe())),
+                }
+
+                if !matches!(object.properties.get("z"), Some(PropertyValue::IntValue(z)) if *z >= 0)
+                {
+                    report.error(map_path, format!("{} has no z", describe()));
+                }
+            }
+
+            if object.user_type == map_classes::SIGN
+                && !matches!(
+                    object.properties.get("id"),
+                    Some(PropertyValue::IntValue(_))
+                )
+            {
+                report.error(map_path, format!("{} has no id", describe()));
+            }
+        }
+    }
+}
+
+fn check_maps(prototypes: &HashMap<String, Prototype>, report: &mut Report) {
+    let mut files = Vec::new();
+    files_with_extension(Path::new(ASSETS_DIR), "tmx", &mut files);
+    files.sort();
+
+    for path in files {
+        let map = match tmx::load_map(&path) {
+            Ok(map) => map,
+            Err(e) => {
+                report.error(&path, format!("Could not load map: {e}"));
+                continue;
+            }
+        };
+
+        for tileset in map.tilesets().iter() {
+            if let Some(image) = &tileset.image {
+                check_image(&path, image, report);
+            }
+
+            for (_, tile) in tileset.tiles() {
+                if let Some(image) = &tile.image {
+                    check_image(&path, image, report);
+                }
+            }
+        }
+
+        check_objects(&path, map.layers(), prototypes, report);
+    }
+}
+
+fn main() {
+    let mut report = Report::default();
+
+    let prototypes = load_prototypes(&mut report);
+    check_prototypes(&prototypes, &mut report);
+    check_dialogues(&mut report);
+    check_maps(&prototypes, &mut report);
+
+    if report.problems.is_empty() {
+        println!("No problems found.");
+        return;
+    }
+
+    for problem in report.problems.iter() {
+        eprintln!("error: {problem}");
+    }
+    eprintln!("{} problem(s) found.", report.problems.len());
+    std::process::exit(1);
+}
@@ -5,18 +5,38 @@ use serde::{Deserialize, Serialize};
 #[derive(Component, Default)]
 pub struct Empty;
 
+// Characters the player can take control of.
 #[derive(Clone, Component, Default, Serialize, Deserialize, ProtoComponent)]
-pub struct Player;
+pub struct Controllable;
+
+// The character moved by the player's input and followed by the camera and dialogue.
+#[derive(Component, Default)]
+pub struct Controlled;
 
 #[derive(Component, Default)]
 pub struct Actor;
 
+// Entities drawn in front of or behind others based on the y of their feet.
+#[derive(Clone, Component, Default, Serialize, Deserialize, ProtoComponent)]
+pub struct YSort {
+    // Offset from the entity's origin to its feet.
+    pub feet_offset: f32,
+}
+
 pub type NpcId = usize;
 
 #[derive(Default, Clone, Debug)]
 #[derive(Serialize, Deserialize, Component, ProtoComponent)]
 pub struct NPC(pub NpcId);
 
+// The prototype and Tiled properties an NPC was spawned from.
+#[derive(Component, Debug)]
+pub struct MapNpc {
+    pub name: String,
+    pub class: String,
+    pub properties: tiled::Properties,
+}
+
 #[derive(Default, Clone, PartialEq, Debug)]
 #[derive(Serialize, Deserialize)]
 pub enum AIKind {
@@ -25,6 +45,8 @@ pub enum AIKind {
 
     RunAway,
     Talking,
+    // Follows the controlled character, given to characters released from control.
+    Follow,
 }
 
 #[derive(Default, Clone, Debug)]
@@ -95,6 +117,9 @@ pub struct FPSTextMarker;
 #[derive(Component, Default)]
 pub struct SignTextMarker;
 
+#[derive(Component, Default)]
+pub struct NavGridOverlayMarker;
+
 #[derive(Component, Reflect)]
 pub struct DialogueEntityWrapper(pub Entity); // dialogue box entt
 
@@ -107,8 +132,10 @@ pub struct HintEntityWrapper(pub Entity);
 #[derive(Component, PartialEq)]
 pub struct EntityPair(pub Entity, pub Entity);
 
+// A sign of `SignsPool`, by the map it's on and its index among the map's signs.
 #[derive(Component)]
 pub struct Sign {
+    pub map: Entity,
     pub handle: usize,
 }
 
@@ -1,14 +1,12 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::path::{Path, PathBuf};
 
 use bevy::prelude::*;
-use bevy_proto::ProtoComponent;
-use serde::{Deserialize, Serialize};
 
 mod parser;
 
 type ParticipantID = usize;
 
-#[derive(Default, Debug)]
+#[derive(Default, Clone, Debug)]
 pub struct Participant {
     pub name: String,
     pub short_name: Option<String>,
@@ -24,20 +22,20 @@ impl From<String> for Participant {
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Clone, Debug)]
 pub struct Line {
     pub author: ParticipantID,
     pub text: String,
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Clone, Debug)]
 pub enum DialogueTree {
     #[default]
     Empty,
     List(Vec<Line>),
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Clone, Debug)]
 #[derive(Component)]
 pub struct Dialogue {
     pub exchanges: Vec<DialogueTree>,
@@ -48,19 +46,22 @@ pub struct Dialogue {
 
 impl Dialogue {
     pub fn new(filename: PathBuf) -> Self {
-        let mut file;
-        if let Ok(f) = std::fs::File::open(&filename) {
-            file = std::io::BufReader::new(f);
-        } else {
-            log::error!("Failed to open dialogue file {:?}", filename);
-            return Dialogue::default()
+        match Dialogue::load(&filename) {
+            Ok(d) => d,
+            Err(e) => {
+                log::error!("{}", e);
+                Dialogue::default()
+            }
         }
+    }
 
-        if let Some(d) = parser::parse_dialogue_file(&mut file) {
-            d
-        } else {
-            log::error!("Failed to parse dialogue file {:?}", filename);
-            Dialogue::default()
-        }
+    pub fn load(filename: &Path) -> Result<Self, String> {
+        let mut file = match std::fs::File::open(filename) {
+            Ok(f) => std::io::BufReader::new(f),
+            Err(_) => return Err(format!("Failed to open dialogue file {:?}", filename)),
+        };
+
+        parser::parse_dialogue_file(&mut file)
+            .ok_or_else(|| format!("Failed to parse dialogue file {:?}", filename))
     }
 }
\ No newline at end of file
@@ -0,0 +1,22 @@
+// The parts of the game that only read and check data files, without a running app:
+// dialogues, maps and the YAML side of prototypes. Shared by the game and prototyp-check.
+
+pub mod dialogue;
+pub mod tmx;
+
+// Prototype files before they're handed to bevy_proto. The game's
+// `prototypes` module re-exports these next to its components.
+pub mod prototypes {
+    pub mod extends;
+    pub mod overrides;
+    pub mod validate;
+}
+
+// The Tiled classes of the objects the game pools instead of spawning with their
+// layer, see `register_pooled_tiled_class`.
+pub mod map_classes {
+    // Spawns the prototype named by its `id` property.
+    pub const NPC: &str = "npc";
+    // Shows the text numbered by its `id` property.
+    pub const SIGN: &str = "sign";
+}
@@ -1,21 +1,23 @@
 use bevy::diagnostic::FrameTimeDiagnosticsPlugin;
 use bevy::prelude::*;
+use bevy::transform::TransformSystem;
 use bevy_ecs_tilemap::prelude::*;
 use bevy_egui::EguiPlugin;
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
-use bevy_proto::ProtoPlugin;
 use bevy_rapier2d::prelude::*;
-use resources::{CursorPos, SignsPool, TilesProperties, UiSettings, NpcPool, VariablePool};
+use resources::{CursorPos, SignsPool, TilesProperties, UiSettings, NpcPool, VariablePool, LogicLayers, StashedNpcs};
 use systems::{PrototypSystemLabel, npc, collision::{self, PhysicsFilterTag, PlayerNpcContantFilter}};
 
 mod components;
-mod dialogue;
 mod prototypes;
 mod resources;
 mod systems;
 mod tiled;
 
-use crate::systems::{animation, debug, movement, setup, sign, text};
+use prototyp::{dialogue, map_classes};
+
+use crate::tiled::{PooledObject, RegisterTiledClass};
+use crate::systems::{animation, control, debug, movement, setup, sign, text, ysort};
 
 fn main() {
     App::new()
@@ -38,13 +40,19 @@ fn main() {
         .add_plugin(WorldInspectorPlugin)
         .add_plugin(TilemapPlugin)
         .add_plugin(tiled::TiledMapPlugin)
+        .register_pooled_tiled_class(map_classes::NPC, PooledObject::Npc)
+        .register_pooled_tiled_class(map_classes::SIGN, PooledObject::Sign)
         .add_plugin(RapierPhysicsPlugin::<&PhysicsFilterTag>::pixels_per_meter(32.0))
         .add_plugin(RapierDebugRenderPlugin::default())
         .add_plugin(FrameTimeDiagnosticsPlugin::default())
-        .add_plugin(ProtoPlugin::default())
+        .add_plugin(prototypes::PrototypesPlugin)
         .insert_resource(PhysicsHooksWithQueryResource(Box::new(PlayerNpcContantFilter)))
         .insert_resource(UiSettings {
             show_debug_window: false,
+            show_nav_grid: false,
+            generate_map: false,
+            export_map: false,
+            show_snapshot_window: false,
         })
         .insert_resource(RapierConfiguration {
             gravity: Vec2::ZERO,
@@ -52,8 +60,10 @@ fn main() {
         })
         .init_resource::<CursorPos>()
         .init_resource::<TilesProperties>()
+        .init_resource::<LogicLayers>()
         .init_resource::<SignsPool>()
         .init_resource::<NpcPool>()
+        .init_resource::<StashedNpcs>()
         .init_resource::<VariablePool>()
         .register_type::<TextureAtlasSprite>()
         .register_type::<PhysicsFilterTag>()
@@ -67,9 +77,15 @@ fn main() {
         .add_system(systems::dialogue::resolve_dialogue)
         .add_system(debug::debug_input)
         .add_system(debug::draw_debug_ui)
+        .add_system(debug::draw_nav_grid_overlay)
+        .add_system(debug::generate_random_map)
+        .add_system(debug::export_current_map)
+        .add_system(debug::draw_snapshot_window)
         .add_system(debug::update_cursor_pos)
+        .add_system(control::switch_controlled.before(PrototypSystemLabel::Movement))
         .add_system(movement::player_movement.label(PrototypSystemLabel::Movement))
         .add_system(movement::ai_movement.label(PrototypSystemLabel::Movement))
+        .add_system(movement::drive_character_controllers.after(PrototypSystemLabel::Movement))
         .add_system(movement::camera_movement)
         .add_system(collision::handle_player_npc_collision)
         .add_system(
@@ -82,5 +98,9 @@ fn main() {
         .add_system(sign::add_sign_sensors)
         .add_system(sign::handle_sign_collision.label(PrototypSystemLabel::SignUpdate))
         .add_system(sign::fix_sign_style.after(PrototypSystemLabel::SignUpdate))
+        .add_system_to_stage(
+            CoreStage::PostUpdate,
+            ysort::y_sort.before(TransformSystem::TransformPropagate),
+        )
         .run();
 }
\ No newline at end of file
@@ -1,30 +1,115 @@
-use bevy::prelude::{AssetServer, Res};
-use bevy_rapier2d::prelude::{Collider, Friction, LockedAxes, RigidBody, Velocity, KinematicCharacterController, ActiveEvents, ActiveCollisionTypes, AdditionalMassProperties, ActiveHooks};
+use bevy::{log, prelude::{AssetServer, Res, Vec2}};
+use bevy_rapier2d::prelude::{Collider, Friction, LockedAxes, RigidBody, Velocity, KinematicCharacterController, ActiveEvents, AdditionalMassProperties, CharacterLength, Ccd, CollisionGroups, Damping, Dominance, GravityScale, Sleeping, ColliderMassProperties, Group, Restitution, Sensor, SolverGroups};
 use serde::{Deserialize, Serialize};
 
 use bevy_proto::prelude::*;
 
-use crate::systems::collision::PhysicsFilterTag;
-
 #[derive(Clone, Serialize, Deserialize)]
 #[serde(tag = "type", content = "value")]
 enum ColliderShape {
+    // Half height and radius, along y.
     Capsule(f32, f32),
+    // Half width and radius, along x.
+    CapsuleX(f32, f32),
+    // Radius.
     Circle(f32),
+    // Half width and half height.
+    Cuboid(f32, f32),
+    // The convex hull of the points.
+    ConvexPolygon(Vec<Vec2>),
+    Compound(Vec<CompoundPart>),
+}
+
+// A shape of a compound collider, placed relative to the entity.
+#[derive(Clone, Serialize, Deserialize)]
+struct CompoundPart {
+    shape: ColliderShape,
+    #[serde(default)]
+    offset: Vec2,
+    // In degrees, counterclockwise.
+    #[serde(default)]
+    rotation: f32,
+}
+
+impl ColliderShape {
+    fn collider(&self) -> Result<Collider, String> {
+        let collider = match self {
+            ColliderShape::Capsule(half_height, radius) => Collider::capsule_y(*half_height, *radius),
+            ColliderShape::CapsuleX(half_width, radius) => Collider::capsule_x(*half_width, *radius),
+            ColliderShape::Circle(radius) => Collider::ball(*radius),
+            ColliderShape::Cuboid(half_width, half_height) => Collider::cuboid(*half_width, *half_height),
+            ColliderShape::ConvexPolygon(points) => Collider::convex_hull(points)
+                .ok_or_else(|| format!("Can't build a convex polygon from {points:?}"))?,
+            ColliderShape::Compound(parts) => {
+                let mut shapes = Vec::new();
+                for part in parts {
+                    if let ColliderShape::Compound(_) = part.shape {
+                        return Err("Compound shapes can't be nested".to_string());
+                    }
+                    shapes.push((part.offset, part.rotation.to_radians(), part.shape.collider()?));
+                }
+                Collider::compound(shapes)
+            }
+        };
+
+        Ok(collider)
+    }
+}
+
+// Collision groups, numbered from 1 to 32.
+#[derive(Clone, Serialize, Deserialize)]
+struct GroupsDef {
+    // The groups the collider is part of, all if left out.
+    memberships: Option<Vec<u32>>,
+    // The groups the collider interacts with, all if left out.
+    filters: Option<Vec<u32>>,
+}
+
+impl GroupsDef {
+    fn group(groups: &Option<Vec<u32>>) -> Result<Group, String> {
+        let Some(groups) = groups else {
+            return Ok(Group::ALL);
+        };
+
+        let mut bits = 0;
+        for group in groups {
+            if !(1..=32).contains(group) {
+                return Err(format!("Collision group {group} isn't between 1 and 32"));
+            }
+            bits |= 1 << (group - 1);
+        }
+
+        Ok(Group::from_bits_truncate(bits))
+    }
+
+    fn groups(&self) -> Result<(Group, Group), String> {
+        Ok((Self::group(&self.memberships)?, Self::group(&self.filters)?))
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize)]
 struct ColliderDef {
     shape: ColliderShape,
     collision_events: Option<bool>,
+    #[serde(default)]
+    sensor: bool,
+    // Which colliders this one generates contacts with.
+    collision_groups: Option<GroupsDef>,
+    // Which colliders this one resolves contacts with.
+    solver_groups: Option<GroupsDef>,
+    restitution: Option<f32>,
+    density: Option<f32>,
 }
 
 #[typetag::serde]
 impl ProtoComponent for ColliderDef {
     fn insert_self(&self, commands: &mut ProtoCommands, _asset_server: &Res<AssetServer>) {
-        let collider = match self.shape {
-            ColliderShape::Capsule(x, y) => Collider::capsule_y(x, y),
-            ColliderShape::Circle(x) => Collider::ball(x / 2.0),
+        let collider = match self.shape.collider() {
+            Ok(collider) => collider,
+            Err(e) => {
+                log::error!("Invalid ColliderDef: {e}");
+                return;
+            }
         };
 
         commands.insert(collider);
@@ -34,6 +119,36 @@ impl ProtoComponent for ColliderDef {
                 commands.insert(ActiveEvents::COLLISION_EVENTS);
             }
         }
+
+        if self.sensor {
+            commands.insert(Sensor);
+        }
+
+        if let Some(groups) = &self.collision_groups {
+            match groups.groups() {
+                Ok((memberships, filters)) => {
+                    commands.insert(CollisionGroups::new(memberships, filters));
+                }
+                Err(e) => log::error!("Invalid collision_groups: {e}"),
+            }
+        }
+
+        if let Some(groups) = &self.solver_groups {
+            match groups.groups() {
+                Ok((memberships, filters)) => {
+                    commands.insert(SolverGroups::new(memberships, filters));
+                }
+                Err(e) => log::error!("Invalid solver_groups: {e}"),
+            }
+        }
+
+        if let Some(restitution) = self.restitution {
+            commands.insert(Restitution::coefficient(restitution));
+        }
+
+        if let Some(density) = self.density {
+            commands.insert(ColliderMassProperties::Density(density));
+        }
     }
 }
 
@@ -49,36 +164,132 @@ impl From<FrictionDef> for Friction {
     }
 }
 
-#[derive(Clone, Serialize, Deserialize, ProtoComponent)]
-#[proto_comp(into = "KinematicCharacterController")]
-struct RapierController;
+// Which axes of a body can't move.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+enum LockedAxis {
+    Rotation,
+    TranslationX,
+    TranslationY,
+}
+
+// Moves kinematic bodies without passing through colliders, see `drive_character_controllers`.
+// Only for `KinematicPositionBased` bodies, the others would also be moved by their velocity.
+#[derive(Clone, Serialize, Deserialize)]
+struct ControllerDef {
+    // The gap kept between the character and obstacles, in pixels.
+    offset: Option<f32>,
+    // Whether to slide along obstacles instead of stopping.
+    #[serde(default = "default_true")]
+    slide: bool,
+    // In degrees.
+    max_slope_climb_angle: Option<f32>,
+    // In degrees.
+    min_slope_slide_angle: Option<f32>,
+    // Push dynamic bodies in the way, such as crates.
+    #[serde(default)]
+    push_dynamic_bodies: bool,
+}
 
-impl From<RapierController> for KinematicCharacterController {
-    fn from(_: RapierController) -> Self {
-        KinematicCharacterController::default()
+impl From<&ControllerDef> for KinematicCharacterController {
+    fn from(def: &ControllerDef) -> Self {
+        let default = KinematicCharacterController::default();
+        KinematicCharacterController {
+            offset: def.offset.map_or(default.offset, CharacterLength::Absolute),
+            slide: def.slide,
+            max_slope_climb_angle: def
+                .max_slope_climb_angle
+                .map_or(default.max_slope_climb_angle, f32::to_radians),
+            min_slope_slide_angle: def
+                .min_slope_slide_angle
+                .map_or(default.min_slope_slide_angle, f32::to_radians),
+            apply_impulse_to_dynamic_bodies: def.push_dynamic_bodies,
+            ..default
+        }
     }
 }
 
+fn default_true() -> bool {
+    true
+}
+
+fn default_mass() -> f32 {
+    1000.0
+}
+
+fn default_locked_axes() -> Vec<LockedAxis> {
+    vec![LockedAxis::Rotation]
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 struct PhysicsDefault {
     kind: RigidBody,
-    mass: Option<f32>,
+    #[serde(default = "default_mass")]
+    mass: f32,
+    #[serde(default)]
+    linear_damping: f32,
+    #[serde(default)]
+    angular_damping: f32,
+    gravity_scale: Option<f32>,
+    // Continuous collision detection, keeps fast bodies from tunneling through thin colliders.
+    #[serde(default)]
+    ccd: bool,
+    // Characters only turn by changing their sprite, so their rotation is locked by default.
+    #[serde(default = "default_locked_axes")]
+    locked_axes: Vec<LockedAxis>,
+    // Bodies of a higher dominance group push lower ones without being pushed back.
+    dominance: Option<i8>,
+    #[serde(default = "default_true")]
+    can_sleep: bool,
+    controller: Option<ControllerDef>,
 }
 
 #[typetag::serde]
 impl ProtoComponent for PhysicsDefault {
     fn insert_self(&self, commands: &mut ProtoCommands, _asset_server: &Res<AssetServer>) {
-        let mass = AdditionalMassProperties::Mass(
-            if self.mass.is_some() {
-                self.mass.unwrap()
-            } else {
-                1000.0
-            }
-        );
+        let mut locked_axes = LockedAxes::empty();
+        for axis in self.locked_axes.iter() {
+            locked_axes |= match axis {
+                LockedAxis::Rotation => LockedAxes::ROTATION_LOCKED,
+                LockedAxis::TranslationX => LockedAxes::TRANSLATION_LOCKED_X,
+                LockedAxis::TranslationY => LockedAxes::TRANSLATION_LOCKED_Y,
+            };
+        }
+
         commands
             .insert(self.kind)
-            .insert(mass)
+            .insert(AdditionalMassProperties::Mass(self.mass))
             .insert(Velocity::default())
-            .insert(LockedAxes::ROTATION_LOCKED);
+            .insert(locked_axes)
+            .insert(Damping {
+                linear_damping: self.linear_damping,
+                angular_damping: self.angular_damping,
+            });
+
+        if let Some(gravity_scale) = self.gravity_scale {
+            commands.insert(GravityScale(gravity_scale));
+        }
+
+        if self.ccd {
+            commands.insert(Ccd::enabled());
+        }
+
+        if let Some(dominance) = self.dominance {
+            commands.insert(Dominance::group(dominance));
+        }
+
+        if !self.can_sleep {
+            commands.insert(Sleeping::disabled());
+        }
+
+        if let Some(controller) = &self.controller {
+            if self.kind == RigidBody::KinematicPositionBased {
+                commands.insert(KinematicCharacterController::from(controller));
+            } else {
+                log::error!(
+                    "Leaving out the character controller, it needs a KinematicPositionBased body instead of {:?}",
+                    self.kind
+                );
+            }
+        }
     }
 }
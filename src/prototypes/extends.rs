@@ -0,0 +1,148 @@
+// Prototype inheritance. A prototype can name other prototypes under `extends`
+// and inherits their components:
+//
+//   name: "patient"
+//   extends: base_character        # or a list: [base_character, base_npc]
+//   components:
+//     - type: Speed
+//       value: 121.0
+//
+// Bases are looked up as `<name>.yaml` in the prototypes directory and may extend other
+// prototypes themselves. They're applied in the listed order and the prototype's own
+// components come last, each overriding what came before it. A component whose type was
+// already inherited is merged into the inherited one: mappings are merged key by key and
+// any other value replaces the inherited value. `remove: true` drops an inherited component.
+// The `children` of the bases are inherited as well, followed by the prototype's own.
+//
+// Part of the library, so prototyp-check resolves prototypes the same way.
+
+use std::path::Path;
+
+use serde_yaml::{Mapping, Value};
+
+const EXTENDS: &str = "extends";
+const COMPONENTS: &str = "components";
+const CHILDREN: &str = "children";
+const REMOVE: &str = "remove";
+
+// Parses the prototype in `data` and replaces its `extends` with the inherited components.
+pub fn resolve(data: &str, dir: &Path) -> Result<Value, String> {
+    let prototype: Value = serde_yaml::from_str(data).map_err(|e| e.to_string())?;
+    let name = prototype
+        .get("name")
+        .and_then(|name| name.as_str())
+        .unwrap_or_default()
+        .to_string();
+
+    resolve_value(prototype, dir, &mut vec![name])
+}
+
+// `chain` holds the prototypes being resolved, to catch prototypes extending themselves.
+fn resolve_value(mut prototype: Value, dir: &Path, chain: &mut Vec<String>) -> Result<Value, String> {
+    let Value::Mapping(map) = &mut prototype else {
+        return Err("A prototype must be a mapping".to_string());
+    };
+
+    let bases = match map.remove(&Value::from(EXTENDS)) {
+        None => Vec::new(),
+        Some(Value::String(base)) => vec![base],
+        Some(Value::Sequence(bases)) => bases
+            .into_iter()
+            .map(|base| match base {
+                Value::String(base) => Ok(base),
+                other => Err(format!("Expected the name of a prototype to extend, found {other:?}")),
+            })
+            .collect::<Result<_, _>>()?,
+        Some(other) => {
+            return Err(format!("Expected the name of a prototype to extend, found {other:?}"));
+        }
+    };
+
+    let mut components = Vec::new();
+    let mut children = Vec::new();
+    for base in bases {
+        let base = load_base(&base, dir, chain)?;
+        merge_components(&mut components, list_of(&base, COMPONENTS)?);
+        children.extend(list_of(&base, CHILDREN)?);
+    }
+
+    merge_components(&mut components, list_of(&prototype, COMPONENTS)?);
+    children.extend(list_of(&prototype, CHILDREN)?);
+
+    if let Value::Mapping(map) = &mut prototype {
+        map.insert(Value::from(COMPONENTS), Value::Sequence(components));
+        if !children.is_empty() {
+            map.insert(Value::from(CHILDREN), Value::Sequence(children));
+        }
+    }
+
+    Ok(prototype)
+}
+
+fn load_base(name: &str, dir: &Path, chain: &mut Vec<String>) -> Result<Value, String> {
+    if chain.iter().any(|n| n == name) {
+        return Err(format!("Prototype {name} extends itself: {} -> {name}", chain.join(" -> ")));
+    }
+
+    let path = dir.join(format!("{name}.yaml"));
+    let base = std::fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|data| serde_yaml::from_str(&data).map_err(|e| e.to_string()))
+        .map_err(|e| format!("Could not load base prototype {name} from {}: {e}", path.display()))?;
+
+    chain.push(name.to_string());
+    let base = resolve_value(base, dir, chain);
+    chain.pop();
+
+    base
+}
+
+// A list of a prototype, such as its components. An empty `components:` is null.
+fn list_of(prototype: &Value, key: &str) -> Result<Vec<Value>, String> {
+    match prototype.get(key) {
+        None | Some(Value::Null) => Ok(Vec::new()),
+        Some(Value::Sequence(list)) => Ok(list.clone()),
+        Some(other) => Err(format!("Expected a list of {key}, found {other:?}")),
+    }
+}
+
+fn merge_components(inherited: &mut Vec<Value>, overrides: Vec<Value>) {
+    for mut component in overrides {
+        let remove = match &mut component {
+            Value::Mapping(map) => map.remove(&Value::from(REMOVE)) == Some(Value::Bool(true)),
+            _ => false,
+        };
+
+        let component_type = component.get("type").cloned();
+        let existing = component_type
+            .as_ref()
+            .and_then(|t| inherited.iter().position(|c| c.get("type") == Some(t)));
+
+        match (existing, remove) {
+            (Some(i), true) => {
+                inherited.remove(i);
+            }
+            (Some(i), false) => merge_values(&mut inherited[i], component),
+            (None, true) => {}
+            (None, false) => inherited.push(component),
+        }
+    }
+}
+
+fn merge_values(inherited: &mut Value, value: Value) {
+    match (inherited, value) {
+        (Value::Mapping(inherited), Value::Mapping(value)) => merge_mappings(inherited, value),
+        (inherited, value) => *inherited = value,
+    }
+}
+
+fn merge_mappings(inherited: &mut Mapping, value: Mapping) {
+    for (key, value) in value {
+        match inherited.get_mut(&key) {
+            Some(existing) => merge_values(existing, value),
+            None => {
+                inherited.insert(key, value);
+            }
+        }
+    }
+}
@@ -1,53 +1,423 @@
-use bevy::prelude::{AssetServer, Commands, Res, BuildChildren, Entity};
-use bevy_proto::prelude::ProtoData;
-use relative_path::RelativePath;
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use bevy::{log, prelude::*};
+use bevy_proto::prelude::{
+    ProtoComponent, ProtoData, ProtoDataOptions, ProtoDeserializer, ProtoPlugin, Prototype,
+    Prototypical,
+};
+use serde::Deserialize;
+
+pub use prototyp::prototypes::{extends, overrides, validate};
 
 pub mod animation;
 pub mod collider;
 pub mod common;
 pub mod npc;
+mod reload;
+pub mod snapshot;
 pub mod sprite;
 
-pub fn spawn_prototype(name: &str, mut commands: &mut Commands, asset_server: &Res<AssetServer>, proto_data: &Res<ProtoData>) -> Entity {
-    let proto = proto_data
-        .get_prototype(name)
-        .expect(&format!("Expected {} prototype!", name));
-    let id = proto
-        .spawn(&mut commands, &proto_data, &asset_server)
-        .id();
+pub const PROTOTYPES_DIR: &str = "assets/prototypes";
+const ASSETS_DIR: &str = "assets";
+
+// A child entity spawned along with a prototype, listed under `children:`:
+//
+//   children:
+//     - prototype: player.child.physics
+//       translation: [0.0, -12.0, 0.0]
+//       children:
+//         - prototype: player.child.hitbox
+//
+// The child prototype's own children are spawned as well.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PrototypeChild {
+    pub prototype: String,
+    #[serde(default)]
+    pub translation: Vec3,
+    // In degrees, counterclockwise.
+    #[serde(default)]
+    pub rotation: f32,
+    #[serde(default = "PrototypeChild::default_scale")]
+    pub scale: Vec2,
+    #[serde(default)]
+    pub children: Vec<PrototypeChild>,
+}
+
+impl PrototypeChild {
+    fn default_scale() -> Vec2 {
+        Vec2::ONE
+    }
+
+    fn transform(&self) -> Transform {
+        Transform::from_translation(self.translation)
+            .with_rotation(Quat::from_rotation_z(self.rotation.to_radians()))
+            .with_scale(self.scale.extend(1.0))
+    }
+}
+
+// The prototype an entity was spawned from.
+#[derive(Component, Clone, Debug)]
+pub struct PrototypeName(pub String);
+
+// Every prototype as written in its file, with its `extends` resolved.
+#[derive(Resource, Default)]
+pub struct ResolvedPrototypes(HashMap<String, serde_yaml::Value>);
+
+impl ResolvedPrototypes {
+    pub fn get(&self, prototype: &str) -> Option<&serde_yaml::Value> {
+        self.0.get(prototype)
+    }
+}
+
+// The children of every prototype, collected while the prototypes are loaded.
+#[derive(Resource, Clone, Default)]
+pub struct PrototypeChildren(Arc<RwLock<HashMap<String, Vec<PrototypeChild>>>>);
+
+impl PrototypeChildren {
+    pub fn get(&self, prototype: &str) -> Vec<PrototypeChild> {
+        self.0
+            .read()
+            .unwrap()
+            .get(prototype)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn insert(&self, prototype: &str, children: Vec<PrototypeChild>) {
+        self.0
+            .write()
+            .unwrap()
+            .insert(prototype.to_string(), children);
+    }
+}
+
+// Deserializes prototypes with their `extends` resolved, see `extends`.
+// Their `children` are taken out and stored in `PrototypeChildren`.
+// Prototypes which failed validation are skipped.
+#[derive(Clone)]
+pub struct PrototypeDeserializer {
+    children: PrototypeChildren,
+    invalid: Arc<RwLock<HashSet<String>>>,
+}
+
+impl PrototypeDeserializer {
+    fn load(&self, data: &str) -> Result<Option<Prototype>, String> {
+        let mut prototype = extends::resolve(data, Path::new(PROTOTYPES_DIR))?;
+
+        let name = prototype.get("name").and_then(|name| name.as_str());
+        if name.map_or(false, |name| self.invalid.read().unwrap().contains(name)) {
+            return Ok(None);
+        }
 
-    let proto_path = RelativePath::new("assets/prototypes");
-    let paths = std::fs::read_dir(proto_path.to_path("."))
-        .expect(&format!("Path {:?} not found!", proto_path.to_path(".")));
+        let children = match &mut prototype {
+            serde_yaml::Value::Mapping(map) => map.remove(&serde_yaml::Value::from("children")),
+            _ => None,
+        };
+        let children: Vec<PrototypeChild> = match children {
+            Some(children) => serde_yaml::from_value(children).map_err(|e| e.to_string())?,
+            None => Vec::new(),
+        };
 
+        let prototype: Prototype = serde_yaml::from_value(prototype).map_err(|e| e.to_string())?;
+        self.children.insert(prototype.name(), children);
+
+        Ok(Some(prototype))
+    }
+}
+
+impl ProtoDeserializer for PrototypeDeserializer {
+    fn deserialize(&self, data: &str) -> Option<Box<dyn Prototypical>> {
+        match self.load(data) {
+            Ok(prototype) => prototype.map(|p| Box::new(p) as Box<dyn Prototypical>),
+            Err(e) => {
+                log::error!("Could not load prototype: {e}");
+                None
+            }
+        }
+    }
+}
+
+// Deserializes each component on its own, so every unknown
+// type and malformed value is reported instead of just the first.
+fn component_problems(prototype: &serde_yaml::Value) -> Vec<validate::Problem> {
+    let Some(components) = prototype.get("components").and_then(|c| c.as_sequence()) else {
+        return Vec::new();
+    };
+
+    components
+        .iter()
+        .enumerate()
+        .filter_map(|(i, component)| {
+            let e = serde_yaml::from_value::<Box<dyn ProtoComponent>>(component.clone()).err()?;
+            Some(validate::Problem {
+                field: format!("components[{i}]"),
+                message: e.to_string(),
+            })
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct ValidatedPrototypes {
+    // Every prototype with its `extends` resolved, by name.
+    resolved: HashMap<String, serde_yaml::Value>,
+    // The prototypes which won't be loaded.
+    invalid: HashSet<String>,
+}
+
+// Validates every prototype and logs all problems along with their file.
+fn validate_prototypes() -> ValidatedPrototypes {
+    let dir = Path::new(PROTOTYPES_DIR);
+    let mut paths: Vec<PathBuf> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().map_or(false, |ext| ext == "yaml"))
+            .collect(),
+        Err(e) => {
+            log::error!("Could not read {}: {e}", dir.display());
+            return ValidatedPrototypes::default();
+        }
+    };
+    paths.sort();
+
+    let mut invalid = HashSet::new();
+    let mut prototypes = Vec::new();
     for path in paths {
-        if let Err(_) = path {
-            continue;
+        let data = match std::fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(e) => {
+                log::error!("{}: {e}", path.display());
+                continue;
+            }
+        };
+
+        // Read before resolving `extends`, so a prototype with a broken base is known by name.
+        let name = serde_yaml::from_str::<serde_yaml::Value>(&data)
+            .ok()
+            .and_then(|prototype| prototype.get("name")?.as_str().map(String::from));
+
+        match extends::resolve(&data, dir) {
+            Ok(prototype) => prototypes.push((path, name, prototype)),
+            Err(e) => {
+                log::error!("{}: {e}", path.display());
+                invalid.extend(name);
+            }
         }
+    }
 
-        let path = path.unwrap().file_name();
-        let path = path.to_str().unwrap();
-        if !path.starts_with(&format!("{}.child", name)) {
-            continue;
+    let names: HashSet<String> = prototypes
+        .iter()
+        .filter_map(|(_, name, _)| name.clone())
+        .collect();
+
+    let mut resolved = HashMap::new();
+    for (path, name, prototype) in prototypes {
+        let mut problems = validate::validate(&prototype, &names, Path::new(ASSETS_DIR));
+        problems.extend(component_problems(&prototype));
+
+        for problem in problems.iter() {
+            log::error!("{}: {problem}", path.display());
         }
 
-        let last_dot = path.rfind(".");
-        if let None = last_dot {
+        let Some(name) = name else {
             continue;
+        };
+        if !problems.is_empty() {
+            invalid.insert(name.clone());
         }
+        resolved.insert(name, prototype);
+    }
+
+    if !invalid.is_empty() {
+        let mut invalid: Vec<_> = invalid.iter().map(String::as_str).collect();
+        invalid.sort();
+        log::error!("Not loading the invalid prototypes {}", invalid.join(", "));
+    }
+
+    ValidatedPrototypes { resolved, invalid }
+}
+
+// Loads the prototypes of assets/prototypes.
+pub struct PrototypesPlugin;
+
+impl Plugin for PrototypesPlugin {
+    fn build(&self, app: &mut App) {
+        let children = PrototypeChildren::default();
+        let validated = validate_prototypes();
+        let invalid = Arc::new(RwLock::new(validated.invalid));
+
+        app.insert_resource(children.clone())
+            .insert_resource(ResolvedPrototypes(validated.resolved))
+            .insert_resource(reload::PrototypeReload::new(invalid.clone()))
+            .add_plugin(ProtoPlugin {
+                options: Some(ProtoDataOptions {
+                    directories: vec![PROTOTYPES_DIR.to_string()],
+                    recursive_loading: false,
+                    deserializer: Box::new(PrototypeDeserializer { children, invalid }),
+                    extensions: Some(vec!["yaml"]),
+                }),
+            })
+            .add_system(reload::reload_prototypes)
+            .add_system(reload::patch_reloaded_entities.after(reload::reload_prototypes))
+            .add_event::<snapshot::SnapshotPrototype>()
+            .add_system(snapshot::snapshot_prototypes);
+    }
+}
+
+pub fn spawn_prototype(
+    name: &str,
+    commands: &mut Commands,
+    asset_server: &Res<AssetServer>,
+    proto_data: &Res<ProtoData>,
+    prototype_children: &PrototypeChildren,
+) -> Option<Entity> {
+    let Some(proto) = proto_data.get_prototype(name) else {
+        log::error!("There's no prototype {name}");
+        return None;
+    };
+    let id = proto
+        .spawn(commands, proto_data, asset_server)
+        .insert(PrototypeName(name.to_string()))
+        .id();
 
-        let path = &path[0..last_dot.unwrap()];
+    let mut chain = vec![name.to_string()];
+    spawn_children(
+        id,
+        &prototype_children.get(name),
+        commands,
+        asset_server,
+        proto_data,
+        prototype_children,
+        &mut chain,
+    );
 
-        let child_proto = proto_data
-            .get_prototype(path)
-            .expect(&format!("Expected {path} prototype!"));
+    Some(id)
+}
+
+// `chain` holds the prototypes being spawned, so a prototype containing itself is caught.
+fn spawn_children(
+    parent: Entity,
+    children: &[PrototypeChild],
+    commands: &mut Commands,
+    asset_server: &Res<AssetServer>,
+    proto_data: &Res<ProtoData>,
+    prototype_children: &PrototypeChildren,
+    chain: &mut Vec<String>,
+) {
+    for child in children {
+        if chain.contains(&child.prototype) {
+            log::error!(
+                "Prototype {} contains itself: {} -> {}",
+                child.prototype,
+                chain.join(" -> "),
+                child.prototype
+            );
+            continue;
+        }
+
+        let Some(child_proto) = proto_data.get_prototype(&child.prototype) else {
+            log::error!(
+                "Child prototype {} of {} not found",
+                child.prototype,
+                chain.join(" -> ")
+            );
+            continue;
+        };
 
         let child_id = child_proto
-            .spawn(&mut commands, &proto_data, &asset_server)
+            .spawn(commands, proto_data, asset_server)
+            .insert(SpatialBundle::from_transform(child.transform()))
+            .insert(PrototypeName(child.prototype.clone()))
             .id();
+        commands.entity(parent).add_child(child_id);
 
-        commands.entity(id).add_child(child_id);
+        chain.push(child.prototype.clone());
+        spawn_children(
+            child_id,
+            &prototype_children.get(&child.prototype),
+            commands,
+            asset_server,
+            proto_data,
+            prototype_children,
+            chain,
+        );
+        spawn_children(
+            child_id,
+            &child.children,
+            commands,
+            asset_server,
+            proto_data,
+            prototype_children,
+            chain,
+        );
+        chain.pop();
     }
+}
+
+// Inserts the components of the prototype `name` that `properties`
+// override on `entity`, which was spawned from it. See `overrides`.
+pub fn insert_overrides(
+    entity: Entity,
+    name: &str,
+    properties: &tiled::Properties,
+    commands: &mut Commands,
+    asset_server: &Res<AssetServer>,
+    proto_data: &Res<ProtoData>,
+    resolved: &ResolvedPrototypes,
+) {
+    let Some(prototype) = resolved.get(name) else {
+        return;
+    };
+
+    let components = match overrides::component_overrides(prototype, properties) {
+        Ok(components) => components,
+        Err(e) => {
+            log::error!("Could not override {name}: {e}");
+            return;
+        }
+    };
+
+    // Checked one by one, so a bad override doesn't drop the others.
+    let components: Vec<serde_yaml::Value> = components
+        .into_iter()
+        .filter(|component| {
+            match serde_yaml::from_value::<Box<dyn ProtoComponent>>(component.clone()) {
+                Ok(_) => true,
+                Err(e) => {
+                    log::error!("Could not override {name}: {e}");
+                    false
+                }
+            }
+        })
+        .collect();
 
-    id
-}
\ No newline at end of file
+    if components.is_empty() {
+        return;
+    }
+
+    let names: HashSet<String> = resolved.0.keys().cloned().collect();
+    let overridden = overrides::apply_overrides(prototype, &components);
+    let problems = validate::validate(&overridden, &names, Path::new(ASSETS_DIR));
+    if !problems.is_empty() {
+        for problem in problems {
+            log::error!("Could not override {name}: {problem}");
+        }
+        return;
+    }
+
+    let mut overrides = serde_yaml::Mapping::new();
+    overrides.insert("name".into(), name.into());
+    overrides.insert("components".into(), serde_yaml::Value::Sequence(components));
+
+    match serde_yaml::from_value::<Prototype>(serde_yaml::Value::Mapping(overrides)) {
+        Ok(overrides) => {
+            overrides.insert(commands.entity(entity), proto_data, asset_server);
+        }
+        Err(e) => log::error!("Could not override {name}: {e}"),
+    }
+}
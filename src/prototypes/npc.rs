@@ -1,4 +1,4 @@
-use bevy::{prelude::{Component, AssetServer, Res, BuildChildren, SpatialBundle}, reflect::Reflect};
+use bevy::{log, prelude::{Component, AssetServer, Res, BuildChildren, SpatialBundle}, reflect::Reflect};
 use bevy_proto::prelude::{ProtoComponent, ProtoCommands};
 use bevy_rapier2d::prelude::{Collider, Sensor, ActiveEvents};
 use serde::{Serialize, Deserialize};
@@ -14,13 +14,20 @@ impl Default for Speed {
     }
 }
 
+// The sensor spawned as a child of talking NPCs, respawned when their prototype is reloaded.
+#[derive(Component)]
+pub struct TalkSensor;
+
 #[typetag::serde]
 impl ProtoComponent for AI {
     fn insert_self(&self, commands: &mut ProtoCommands, _: &Res<AssetServer>) {
         match self.kind {
-        AIKind::None => { unreachable!() },
-        AIKind::RunAway => {
-            commands.insert(AI{ kind: AIKind::RunAway});
+        // Rejected by validation, but a bad prototype shouldn't take down the game.
+        AIKind::None => {
+            log::error!("AI kind None isn't valid");
+        },
+        AIKind::RunAway | AIKind::Follow => {
+            commands.insert(self.clone());
         },
         AIKind::Talking => {
             commands
@@ -30,7 +37,8 @@ impl ProtoComponent for AI {
                         .spawn(Collider::ball(32.0))
                         .insert(Sensor)
                         .insert(ActiveEvents::COLLISION_EVENTS)
-                        .insert(SpatialBundle::default());
+                        .insert(SpatialBundle::default())
+                        .insert(TalkSensor);
             });
         },    
         }
@@ -0,0 +1,121 @@
+// Per-instance overrides of a prototype's components from the properties of a Tiled object.
+// A property named after a component replaces its value, while `Component.field` sets
+// a single field, nested as deep as needed:
+//
+//   Speed = 150.0
+//   NameDef = "Bob"
+//   AI.kind = "Talking"
+//   ColliderDef.shape.value = "[10.0, 5.0]"
+//
+// Strings holding a YAML list or mapping are parsed. Properties starting with a lower case
+// letter, such as `id` and `z`, aren't overrides. Components which load assets when the
+// prototypes are loaded, like SpriteSheetBundleDef, can't be overridden.
+//
+// Part of the library, so prototyp-check checks overrides the same way.
+
+use serde_yaml::{Mapping, Value};
+use tiled::{Properties, PropertyValue};
+
+// The dialogue file of an NPC, relative to assets/dialogues.
+pub const DIALOGUE: &str = "dialogue";
+
+pub fn is_override(property: &str) -> bool {
+    property.starts_with(|c: char| c.is_ascii_uppercase())
+}
+
+fn property_value(property: &PropertyValue) -> Value {
+    match property {
+        PropertyValue::BoolValue(v) => Value::from(*v),
+        PropertyValue::FloatValue(v) => Value::from(*v),
+        PropertyValue::IntValue(v) => Value::from(*v),
+        PropertyValue::ObjectValue(v) => Value::from(*v),
+        PropertyValue::ColorValue(c) => Value::from(format!(
+            "#{:02x}{:02x}{:02x}{:02x}",
+            c.alpha, c.red, c.green, c.blue
+        )),
+        PropertyValue::StringValue(s) | PropertyValue::FileValue(s) => match serde_yaml::from_str(s) {
+            Ok(value @ (Value::Sequence(_) | Value::Mapping(_))) => value,
+            _ => Value::from(s.as_str()),
+        },
+    }
+}
+
+fn set_field(target: &mut Value, fields: &[&str], value: Value) -> Result<(), String> {
+    let Some((field, rest)) = fields.split_first() else {
+        *target = value;
+        return Ok(());
+    };
+
+    if target.is_null() {
+        *target = Value::Mapping(Mapping::new());
+    }
+
+    let Value::Mapping(map) = target else {
+        return Err(format!("Can't set {field} of {target:?}"));
+    };
+
+    let key = Value::from(*field);
+    if !map.contains_key(&key) {
+        map.insert(key.clone(), Value::Null);
+    }
+
+    set_field(map.get_mut(&key).unwrap(), rest, value)
+}
+
+// The components of `prototype`, with its `extends` resolved, that `properties` override.
+// Fields which aren't overridden keep the prototype's values.
+pub fn component_overrides(prototype: &Value, properties: &Properties) -> Result<Vec<Value>, String> {
+    let inherited = prototype
+        .get("components")
+        .and_then(|c| c.as_sequence())
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+
+    // Sorted, so `AI = ...` is applied before `AI.kind = ...`.
+    let mut names: Vec<&String> = properties.keys().filter(|name| is_override(name)).collect();
+    names.sort();
+
+    let mut overrides: Vec<Value> = Vec::new();
+    for name in names {
+        let mut path = name.split('.');
+        let component_type = path.next().unwrap_or_default();
+        let fields: Vec<&str> = std::iter::once("value").chain(path).collect();
+
+        let is_component = |c: &&Value| c.get("type").and_then(|t| t.as_str()) == Some(component_type);
+        let i = match overrides.iter().position(|c| is_component(&c)) {
+            Some(i) => i,
+            None => {
+                let component = inherited.iter().find(is_component).cloned().unwrap_or_else(|| {
+                    let mut component = Mapping::new();
+                    component.insert(Value::from("type"), Value::from(component_type));
+                    Value::Mapping(component)
+                });
+                overrides.push(component);
+                overrides.len() - 1
+            }
+        };
+
+        set_field(&mut overrides[i], &fields, property_value(&properties[name]))
+            .map_err(|e| format!("{name}: {e}"))?;
+    }
+
+    Ok(overrides)
+}
+
+// `prototype` with the `overrides` replacing its components of the same type.
+pub fn apply_overrides(prototype: &Value, overrides: &[Value]) -> Value {
+    let mut prototype = prototype.clone();
+    let Some(components) = prototype.get_mut("components").and_then(|c| c.as_sequence_mut()) else {
+        return prototype;
+    };
+
+    for component in overrides {
+        let component_type = component.get("type");
+        match components.iter_mut().find(|c| c.get("type") == component_type) {
+            Some(existing) => *existing = component.clone(),
+            None => components.push(component.clone()),
+        }
+    }
+
+    prototype
+}
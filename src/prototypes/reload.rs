@@ -0,0 +1,186 @@
+// Reloads prototypes when their files change and patches the entities spawned from them.
+// The components of the changed prototypes are inserted again, while the position,
+// velocity and animation state of the entities are kept and the overrides of NPCs placed
+// in Tiled are applied again. Components which aren't part of the prototype, such as
+// dialogues, are left alone. Children aren't respawned.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+
+use bevy::{log, prelude::*};
+use bevy_proto::prelude::{ProtoData, Prototypical};
+use bevy_rapier2d::prelude::Velocity;
+
+use crate::components::{AnimationState, Direction, MapNpc};
+
+use super::{
+    insert_overrides, npc::TalkSensor, validate_prototypes, PrototypeName, ResolvedPrototypes,
+    PROTOTYPES_DIR,
+};
+
+// How often the prototype files are checked for changes.
+const POLL_INTERVAL: f32 = 0.5;
+
+#[derive(Resource)]
+pub struct PrototypeReload {
+    timer: Timer,
+    modified: HashMap<PathBuf, SystemTime>,
+    // Shared with `PrototypeDeserializer`.
+    invalid: Arc<RwLock<HashSet<String>>>,
+    // Reloaded prototypes whose entities are yet to be patched.
+    changed: Vec<String>,
+}
+
+impl PrototypeReload {
+    pub fn new(invalid: Arc<RwLock<HashSet<String>>>) -> Self {
+        Self {
+            timer: Timer::from_seconds(POLL_INTERVAL, TimerMode::Repeating),
+            modified: modified_times(),
+            invalid,
+            changed: Vec::new(),
+        }
+    }
+}
+
+fn modified_times() -> HashMap<PathBuf, SystemTime> {
+    let Ok(entries) = std::fs::read_dir(PROTOTYPES_DIR) else {
+        return HashMap::new();
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| Some((entry.path(), entry.metadata().ok()?.modified().ok()?)))
+        .collect()
+}
+
+pub fn reload_prototypes(world: &mut World) {
+    let delta = world.resource::<Time>().delta();
+    let mut reload = world.resource_mut::<PrototypeReload>();
+    if !reload.timer.tick(delta).just_finished() {
+        return;
+    }
+
+    let modified = modified_times();
+    if modified == reload.modified {
+        return;
+    }
+    reload.modified = modified;
+
+    // A change to a base affects all prototypes extending it,
+    // so the prototypes are compared with their bases resolved.
+    let validated = validate_prototypes();
+    let resolved = world.resource::<ResolvedPrototypes>();
+    let mut changed: Vec<String> = validated
+        .resolved
+        .iter()
+        .filter(|(name, prototype)| resolved.get(name) != Some(*prototype))
+        .map(|(name, _)| name.clone())
+        .collect();
+    changed.sort();
+
+    if changed.is_empty() {
+        return;
+    }
+
+    // Keep the last working prototypes while an edit is broken.
+    let broken: Vec<&str> = changed
+        .iter()
+        .filter(|name| validated.invalid.contains(*name))
+        .map(String::as_str)
+        .collect();
+    if !broken.is_empty() {
+        log::error!(
+            "Not reloading prototypes until {} are fixed",
+            broken.join(", ")
+        );
+        return;
+    }
+
+    log::info!("Reloading prototypes {}", changed.join(", "));
+    world.insert_resource(ResolvedPrototypes(validated.resolved));
+    let mut reload = world.resource_mut::<PrototypeReload>();
+    *reload.invalid.write().unwrap() = validated.invalid;
+    reload.changed.extend(changed);
+
+    let proto_data = ProtoData::from_world(world);
+    world.insert_resource(proto_data);
+}
+
+pub fn patch_reloaded_entities(
+    mut commands: Commands,
+    mut reload: ResMut<PrototypeReload>,
+    asset_server: Res<AssetServer>,
+    proto_data: Res<ProtoData>,
+    resolved: Res<ResolvedPrototypes>,
+    entity_q: Query<(
+        Entity,
+        &PrototypeName,
+        Option<&Transform>,
+        Option<&Velocity>,
+        Option<&AnimationState>,
+        Option<&Direction>,
+        Option<&Children>,
+        Option<&MapNpc>,
+    )>,
+    sensor_q: Query<(), With<TalkSensor>>,
+) {
+    if reload.changed.is_empty() {
+        return;
+    }
+    let changed: HashSet<String> = reload.changed.drain(..).collect();
+
+    for (entity, name, transform, velocity, animation_state, direction, children, map_npc) in
+        entity_q.iter()
+    {
+        if !changed.contains(&name.0) {
+            continue;
+        }
+
+        let Some(proto) = proto_data.get_prototype(&name.0) else {
+            continue;
+        };
+
+        // Spawned again along with the AI.
+        if let Some(children) = children {
+            for child in children.iter() {
+                if sensor_q.contains(*child) {
+                    commands.entity(*child).despawn_recursive();
+                }
+            }
+        }
+
+        proto.insert(commands.entity(entity), &proto_data, &asset_server);
+
+        // The variations set up in Tiled win over the reloaded prototype, as when the NPC was spawned.
+        if let Some(map_npc) = map_npc {
+            insert_overrides(
+                entity,
+                &name.0,
+                &map_npc.properties,
+                &mut commands,
+                &asset_server,
+                &proto_data,
+                &resolved,
+            );
+        }
+
+        // Inserting the state again also restarts the animation with the new frames.
+        let mut entity_commands = commands.entity(entity);
+        if let Some(transform) = transform {
+            entity_commands.insert(*transform);
+        }
+        if let Some(velocity) = velocity {
+            entity_commands.insert(*velocity);
+        }
+        if let Some(animation_state) = animation_state {
+            entity_commands.insert(animation_state.clone());
+        }
+        if let Some(direction) = direction {
+            entity_commands.insert(*direction);
+        }
+    }
+}
@@ -0,0 +1,446 @@
+// Writes live entities back to prototype files, so values tweaked in the inspector can be kept.
+// The entity's prototype is the starting point, and components with a live counterpart, such as
+// Speed, NameDef, ColliderDef or EntityAnimationData, take the entity's current values. Others,
+// like SpriteSheetBundleDef, are written as they were loaded. TransformDef is kept as well, as
+// it's where the prototype spawns rather than where the entity has wandered off to.
+//
+// A prototype that extends others keeps its `extends` and `children`, and only the components
+// which differ from the inherited ones are written.
+//
+// The overrides of an NPC placed in Tiled only apply to that NPC, so its own prototype,
+// which every other instance shares, isn't replaced by a snapshot of it.
+
+use std::path::{Path, PathBuf};
+
+use bevy::{log, prelude::*};
+use bevy_rapier2d::prelude::{
+    AdditionalMassProperties, Collider, ColliderMassProperties, Damping, Dominance, Friction,
+    GravityScale, Restitution, RigidBody, Sensor,
+};
+use serde::Serialize;
+use serde_yaml::{Mapping, Value};
+
+use crate::components::{EntityAnimationData, MapNpc, YSort, AI, NPC};
+
+use super::{
+    extends, npc::Speed, overrides::is_override, PrototypeName, ResolvedPrototypes, PROTOTYPES_DIR,
+};
+
+// Writes `entity` to assets/prototypes/<name>.yaml, replacing the file if it exists.
+pub struct SnapshotPrototype {
+    pub entity: Entity,
+    pub name: String,
+}
+
+pub fn snapshot_prototypes(world: &mut World) {
+    let events: Vec<SnapshotPrototype> = world
+        .resource_mut::<Events<SnapshotPrototype>>()
+        .drain()
+        .collect();
+
+    for SnapshotPrototype { entity, name } in events {
+        match snapshot(world, entity, &name) {
+            Ok(path) => log::info!("Wrote prototype {name} to {}", path.display()),
+            Err(e) => log::error!("Could not write {entity:?} to prototype {name}: {e}"),
+        }
+    }
+}
+
+fn snapshot(world: &World, entity: Entity, name: &str) -> Result<PathBuf, String> {
+    if name.is_empty() || name.contains(['/', '\\']) {
+        return Err("Expected a prototype name without slashes".to_string());
+    }
+
+    let prototype = world
+        .get::<PrototypeName>(entity)
+        .ok_or("The entity wasn't spawned from a prototype")?;
+    let resolved = world
+        .resource::<ResolvedPrototypes>()
+        .get(&prototype.0)
+        .ok_or_else(|| format!("There's no prototype {}", prototype.0))?;
+
+    let mut overrides: Vec<&str> = world
+        .get::<MapNpc>(entity)
+        .map(|map_npc| {
+            map_npc
+                .properties
+                .keys()
+                .map(String::as_str)
+                .filter(|property| is_override(property))
+                .collect()
+        })
+        .unwrap_or_default();
+    overrides.sort();
+    if !overrides.is_empty() {
+        if name == prototype.0 {
+            return Err(format!(
+                "Its map overrides {} would apply to every {name}, write it to a new prototype instead",
+                overrides.join(", ")
+            ));
+        }
+        log::warn!(
+            "The snapshot of {entity:?} includes its map overrides {}",
+            overrides.join(", ")
+        );
+    }
+
+    let dir = Path::new(PROTOTYPES_DIR);
+    let path = dir.join(format!("{name}.yaml"));
+
+    // The file being replaced, or else the entity's own, for its `extends` and `children`.
+    let template = [path.clone(), dir.join(format!("{}.yaml", prototype.0))]
+        .iter()
+        .find_map(|path| {
+            let data = std::fs::read_to_string(path).ok()?;
+            serde_yaml::from_str::<Value>(&data).ok()
+        });
+
+    let mut components: Vec<Value> = resolved
+        .get("components")
+        .and_then(|c| c.as_sequence())
+        .cloned()
+        .unwrap_or_default();
+
+    for component in components.iter_mut() {
+        let Some(component_type) = component
+            .get("type")
+            .and_then(|t| t.as_str())
+            .map(str::to_string)
+        else {
+            continue;
+        };
+        let value = component.get("value").cloned().unwrap_or(Value::Null);
+        if let Some(live) = live_value(world, entity, &component_type, value.clone()) {
+            set(component, "value", keep_order(live, &value));
+        }
+    }
+
+    let (bases, children) = match &template {
+        Some(template) => (
+            template.get("extends").cloned(),
+            template.get("children").cloned(),
+        ),
+        None => (None, resolved.get("children").cloned()),
+    };
+
+    let mut snapshot = Mapping::new();
+    snapshot.insert("name".into(), name.into());
+    if let Some(bases) = bases {
+        let mut base = Mapping::new();
+        base.insert("name".into(), name.into());
+        base.insert("extends".into(), bases.clone());
+
+        let data = serde_yaml::to_string(&base).map_err(|e| e.to_string())?;
+        let inherited = extends::resolve(&data, dir)?;
+        let inherited = inherited
+            .get("components")
+            .and_then(|c| c.as_sequence())
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+
+        components = own_components(components, inherited);
+        snapshot.insert("extends".into(), bases);
+    }
+    snapshot.insert("components".into(), Value::Sequence(components));
+    if let Some(children) = children {
+        snapshot.insert("children".into(), children);
+    }
+
+    let data = serde_yaml::to_string(&snapshot).map_err(|e| e.to_string())?;
+    std::fs::write(&path, data).map_err(|e| e.to_string())?;
+
+    Ok(path)
+}
+
+// The components which differ from the `inherited` ones, removing inherited
+// components the entity doesn't have.
+fn own_components(components: Vec<Value>, inherited: &[Value]) -> Vec<Value> {
+    let mut own: Vec<Value> = components
+        .iter()
+        .filter(|component| !inherited.iter().any(|base| same(component, base)))
+        .cloned()
+        .collect();
+
+    for base in inherited {
+        let base_type = base.get("type");
+        if !components
+            .iter()
+            .any(|component| component.get("type") == base_type)
+        {
+            let mut removed = Mapping::new();
+            removed.insert("type".into(), base_type.cloned().unwrap_or(Value::Null));
+            removed.insert("remove".into(), true.into());
+            own.push(Value::Mapping(removed));
+        }
+    }
+
+    own
+}
+
+// Whether two values are the same, regardless of the order of keys and of
+// whether numbers are written as integers or floats.
+fn same(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Mapping(a), Value::Mapping(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, a)| b.get(key).map_or(false, |b| same(a, b)))
+        }
+        (Value::Sequence(a), Value::Sequence(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same(a, b))
+        }
+        (Value::Number(a), Value::Number(b)) => a.as_f64() == b.as_f64(),
+        (a, b) => a == b,
+    }
+}
+
+// `live` with its keys in the order of `loaded`, so rewritten files keep their layout.
+// Keys only `live` has come last.
+fn keep_order(live: Value, loaded: &Value) -> Value {
+    match (live, loaded) {
+        (Value::Mapping(mut live), Value::Mapping(loaded)) => {
+            let mut ordered = Mapping::new();
+            for (key, loaded) in loaded {
+                if let Some(value) = live.remove(key) {
+                    ordered.insert(key.clone(), keep_order(value, loaded));
+                }
+            }
+            ordered.extend(live);
+
+            Value::Mapping(ordered)
+        }
+        (live, _) => live,
+    }
+}
+
+// The value of a component, as it would be written in the prototype, from the entity's
+// current state. None keeps the value it was loaded with.
+fn live_value(
+    world: &World,
+    entity: Entity,
+    component_type: &str,
+    mut value: Value,
+) -> Option<Value> {
+    match component_type {
+        "Speed" => Some(float(world.get::<Speed>(entity)?.0)),
+        "NameDef" => Some(world.get::<Name>(entity)?.as_str().into()),
+        "NPC" => Some(world.get::<NPC>(entity)?.0.into()),
+        "AI" => to_value(world.get::<AI>(entity)?),
+        "YSort" => to_value(world.get::<YSort>(entity)?),
+        "EntityAnimationData" => to_value(world.get::<EntityAnimationData>(entity)?),
+        "FrictionDef" => {
+            set(
+                &mut value,
+                "c",
+                float(world.get::<Friction>(entity)?.coefficient),
+            );
+            Some(value)
+        }
+        "ColliderDef" => {
+            collider_value(world, entity, &mut value)?;
+            Some(value)
+        }
+        "PhysicsDefault" => {
+            physics_value(world, entity, &mut value)?;
+            Some(value)
+        }
+        _ => None,
+    }
+}
+
+fn collider_value(world: &World, entity: Entity, value: &mut Value) -> Option<()> {
+    let collider = world.get::<Collider>(entity)?;
+
+    // Polygons and compounds are written as they were loaded.
+    let shape = if let Some(ball) = collider.as_ball() {
+        Some(("Circle", float(ball.radius())))
+    } else if let Some(cuboid) = collider.as_cuboid() {
+        let half_extents = cuboid.half_extents();
+        Some(("Cuboid", floats(&[half_extents.x, half_extents.y])))
+    } else if let Some(capsule) = collider.as_capsule() {
+        let segment = capsule.segment();
+        let shape_type = if segment.a().x != segment.b().x {
+            "CapsuleX"
+        } else {
+            "Capsule"
+        };
+        Some((
+            shape_type,
+            floats(&[capsule.half_height(), capsule.radius()]),
+        ))
+    } else {
+        None
+    };
+
+    if let Some((shape_type, sizes)) = shape {
+        let mut shape = Mapping::new();
+        shape.insert("type".into(), shape_type.into());
+        shape.insert("value".into(), sizes);
+        set(value, "shape", Value::Mapping(shape));
+    }
+
+    set_unless_default(
+        value,
+        "sensor",
+        world.get::<Sensor>(entity).is_some().into(),
+        false.into(),
+    );
+
+    if let Some(restitution) = world.get::<Restitution>(entity) {
+        set(value, "restitution", float(restitution.coefficient));
+    }
+
+    if let Some(ColliderMassProperties::Density(density)) =
+        world.get::<ColliderMassProperties>(entity)
+    {
+        set(value, "density", float(*density));
+    }
+
+    Some(())
+}
+
+fn physics_value(world: &World, entity: Entity, value: &mut Value) -> Option<()> {
+    set(value, "kind", to_value(world.get::<RigidBody>(entity)?)?);
+
+    if let Some(AdditionalMassProperties::Mass(mass)) =
+        world.get::<AdditionalMassProperties>(entity)
+    {
+        set(value, "mass", float(*mass));
+    }
+
+    if let Some(damping) = world.get::<Damping>(entity) {
+        set_unless_default(
+            value,
+            "linear_damping",
+            float(damping.linear_damping),
+            float(0.0),
+        );
+        set_unless_default(
+            value,
+            "angular_damping",
+            float(damping.angular_damping),
+            float(0.0),
+        );
+    }
+
+    if let Some(gravity_scale) = world.get::<GravityScale>(entity) {
+        set(value, "gravity_scale", float(gravity_scale.0));
+    }
+
+    if let Some(dominance) = world.get::<Dominance>(entity) {
+        set(value, "dominance", dominance.groups.into());
+    }
+
+    Some(())
+}
+
+fn to_value(component: &impl Serialize) -> Option<Value> {
+    serde_yaml::to_value(component)
+        .map_err(|e| log::error!("Could not write a component: {e}"))
+        .ok()
+}
+
+// Goes through the shortest text of the f32, so 0.1 is written as 0.1.
+fn float(value: f32) -> Value {
+    Value::from(value.to_string().parse::<f64>().unwrap_or(value as f64))
+}
+
+fn floats(values: &[f32]) -> Value {
+    Value::Sequence(values.iter().copied().map(float).collect())
+}
+
+fn set(target: &mut Value, key: &str, field: Value) {
+    if !target.is_mapping() {
+        *target = Value::Mapping(Mapping::new());
+    }
+
+    if let Value::Mapping(map) = target {
+        map.insert(key.into(), field);
+    }
+}
+
+// Leaves out fields which were left out of the prototype and still have their default.
+fn set_unless_default(target: &mut Value, key: &str, field: Value, default: Value) {
+    if field != default || target.get(key).is_some() {
+        set(target, key, field);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yaml(data: &str) -> Value {
+        serde_yaml::from_str(data).unwrap()
+    }
+
+    fn components(data: &str) -> Vec<Value> {
+        yaml(data).as_sequence().unwrap().clone()
+    }
+
+    #[test]
+    fn same_ignores_key_order_and_number_types() {
+        assert!(same(
+            &yaml("{a: 1, b: [2.0, x]}"),
+            &yaml("{b: [2, x], a: 1.0}")
+        ));
+        assert!(!same(&yaml("{a: 1}"), &yaml("{a: 1.5}")));
+        assert!(!same(&yaml("{a: 1}"), &yaml("{a: 1, b: 2}")));
+        assert!(!same(&yaml("[1, 2]"), &yaml("[2, 1]")));
+        assert!(!same(&yaml("1"), &yaml("'1'")));
+    }
+
+    #[test]
+    fn own_components_skip_inherited_ones() {
+        let inherited = components(
+            "
+            - type: Speed
+              value: 100
+            - type: YSort
+              value: {feet_offset: 5.0}
+            - type: NPC
+              value: 0
+            ",
+        );
+        let live = components(
+            "
+            - type: Speed
+              value: 100.0
+            - type: YSort
+              value: {feet_offset: 7.5}
+            - type: NameDef
+              value: Bob
+            ",
+        );
+
+        let own = own_components(live, &inherited);
+        assert_eq!(
+            own,
+            components(
+                "
+                - type: YSort
+                  value: {feet_offset: 7.5}
+                - type: NameDef
+                  value: Bob
+                - type: NPC
+                  remove: true
+                "
+            )
+        );
+    }
+
+    #[test]
+    fn keep_order_follows_the_loaded_keys() {
+        let live = yaml("{c: 3, a: {y: 2, x: 1}, b: 2, d: 4}");
+        let loaded = yaml("{a: {x: 0, y: 0}, b: 0, c: 0}");
+
+        let ordered = serde_yaml::to_string(&keep_order(live, &loaded)).unwrap();
+        let keys: Vec<&str> = ordered
+            .lines()
+            .filter_map(|line| line.split(':').next())
+            .map(str::trim)
+            .filter(|key| !key.is_empty() && *key != "---")
+            .collect();
+        assert_eq!(keys, ["a", "x", "y", "b", "c", "d"]);
+    }
+}
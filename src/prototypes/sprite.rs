@@ -1,52 +1,252 @@
-use std::sync::RwLock;
+use std::{collections::{BTreeMap, HashMap}, sync::RwLock};
 
-use bevy::prelude::*;
+use bevy::{log, prelude::*, sprite::Anchor};
 use serde::{Deserialize, Serialize};
 
 use bevy_proto::prelude::*;
 
+// Texture atlases shared by the sprite sheets of all prototypes, by image and layout.
+// Images are reloaded by the asset server, which the atlases pick up through their handle.
+#[derive(Resource, Default)]
+pub struct SpriteSheetCache {
+    atlases: HashMap<String, Handle<TextureAtlas>>,
+}
+
+// The point of a frame placed at the entity's position.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+enum AnchorDef {
+    #[default]
+    Center,
+    BottomLeft,
+    BottomCenter,
+    BottomRight,
+    CenterLeft,
+    CenterRight,
+    TopLeft,
+    TopCenter,
+    TopRight,
+    // Relative to the size of the frame, from (-0.5, -0.5) at the bottom left
+    // to (0.5, 0.5) at the top right.
+    Custom(Vec2),
+}
+
+impl From<AnchorDef> for Anchor {
+    fn from(anchor: AnchorDef) -> Self {
+        match anchor {
+            AnchorDef::Center => Anchor::Center,
+            AnchorDef::BottomLeft => Anchor::BottomLeft,
+            AnchorDef::BottomCenter => Anchor::BottomCenter,
+            AnchorDef::BottomRight => Anchor::BottomRight,
+            AnchorDef::CenterLeft => Anchor::CenterLeft,
+            AnchorDef::CenterRight => Anchor::CenterRight,
+            AnchorDef::TopLeft => Anchor::TopLeft,
+            AnchorDef::TopCenter => Anchor::TopCenter,
+            AnchorDef::TopRight => Anchor::TopRight,
+            AnchorDef::Custom(point) => Anchor::Custom(point),
+        }
+    }
+}
+
+// A frame map in the JSON format of TexturePacker, either as an array or a hash.
+// Frames of a hash are indexed in the order of their names.
+#[derive(Deserialize)]
+struct FrameMap {
+    frames: FrameList,
+    meta: FrameMeta,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FrameList {
+    Array(Vec<FrameEntry>),
+    Hash(BTreeMap<String, FrameEntry>),
+}
+
+#[derive(Deserialize)]
+struct FrameEntry {
+    frame: FrameRect,
+}
+
+#[derive(Deserialize)]
+struct FrameRect {
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+}
+
+#[derive(Deserialize)]
+struct FrameMeta {
+    size: FrameSize,
+}
+
+#[derive(Deserialize)]
+struct FrameSize {
+    w: f32,
+    h: f32,
+}
+
+// How the frames are laid out in the image.
+#[derive(Debug)]
+enum AtlasLayout {
+    Grid {
+        frame_size: Vec2,
+        columns: usize,
+        rows: usize,
+        padding: Option<Vec2>,
+        offset: Option<Vec2>,
+    },
+    Frames {
+        size: Vec2,
+        frames: Vec<Rect>,
+    },
+}
+
+impl AtlasLayout {
+    fn load_frames(path: &str) -> Result<Self, String> {
+        let file = std::path::Path::new("assets").join(path);
+        let frame_map: FrameMap = std::fs::read_to_string(&file)
+            .map_err(|e| e.to_string())
+            .and_then(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+            .map_err(|e| format!("Could not load the frame map {}: {e}", file.display()))?;
+
+        let entries: Vec<FrameEntry> = match frame_map.frames {
+            FrameList::Array(entries) => entries,
+            FrameList::Hash(entries) => entries.into_values().collect(),
+        };
+
+        Ok(AtlasLayout::Frames {
+            size: Vec2::new(frame_map.meta.size.w, frame_map.meta.size.h),
+            frames: entries
+                .iter()
+                .map(|FrameEntry { frame }| {
+                    Rect::new(frame.x, frame.y, frame.x + frame.w, frame.y + frame.h)
+                })
+                .collect(),
+        })
+    }
+
+    fn atlas(self, image: Handle<Image>) -> TextureAtlas {
+        match self {
+            AtlasLayout::Grid { frame_size, columns, rows, padding, offset } => {
+                TextureAtlas::from_grid(image, frame_size, columns, rows, padding, offset)
+            }
+            AtlasLayout::Frames { size, frames } => {
+                let mut atlas = TextureAtlas::new_empty(image, size);
+                for frame in frames {
+                    atlas.add_texture(frame);
+                }
+                atlas
+            }
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Component)]
 struct SpriteSheetBundleDef {
     pub texture_path: HandlePath,
     pub init_sprite: usize,
-    pub tile_size: u32,
-    pub sprite_width: usize,
-    pub sprite_height: usize,
+    // The size of square frames, `frame_size` takes rectangular ones.
+    pub tile_size: Option<u32>,
+    pub frame_size: Option<Vec2>,
+    // The number of columns and rows of frames.
+    pub sprite_width: Option<usize>,
+    pub sprite_height: Option<usize>,
+    // The gap between frames.
+    pub padding: Option<Vec2>,
+    // Where the first frame starts in the image.
+    pub offset: Option<Vec2>,
+    // A JSON frame map relative to assets, used instead of the grid.
+    pub frames: Option<String>,
+    #[serde(default)]
+    pub anchor: AnchorDef,
 
     #[serde(skip)]
     pub atlas_handle: RwLock<Option<Handle<TextureAtlas>>>,
 }
 
+impl SpriteSheetBundleDef {
+    fn layout(&self) -> Result<AtlasLayout, String> {
+        if let Some(frames) = &self.frames {
+            return AtlasLayout::load_frames(frames);
+        }
+
+        let frame_size = self
+            .frame_size
+            .or_else(|| self.tile_size.map(|size| Vec2::splat(size as f32)))
+            .ok_or("Needs a tile_size, frame_size or frames")?;
+        let (Some(columns), Some(rows)) = (self.sprite_width, self.sprite_height) else {
+            return Err("Needs a sprite_width and sprite_height".to_string());
+        };
+
+        Ok(AtlasLayout::Grid {
+            frame_size,
+            columns,
+            rows,
+            padding: self.padding,
+            offset: self.offset,
+        })
+    }
+
+    // Sprite sheets sharing this key share their atlas. Frame maps are keyed by
+    // their frames rather than their path, so an edited one gets a new atlas on reload.
+    fn cache_key(&self, layout: &AtlasLayout) -> String {
+        format!("{}#{layout:?}", self.texture_path.as_str())
+    }
+}
+
 #[typetag::serde]
 impl ProtoComponent for SpriteSheetBundleDef {
     fn insert_self(&self, commands: &mut ProtoCommands, _asset_server: &Res<AssetServer>) {
-        let handle_id = self.atlas_handle.read().unwrap().as_ref().unwrap().id();
-        let atlas_handle: Handle<TextureAtlas> = commands
-            .get_handle(self, handle_id)
-            .expect("Expected sprite sheet to be loaded!");
+        let Some(handle_id) = self.atlas_handle.read().unwrap().as_ref().map(|handle| handle.id()) else {
+            log::error!("The sprite sheet {} wasn't prepared", self.texture_path.as_str());
+            return;
+        };
+        let Some(atlas_handle): Option<Handle<TextureAtlas>> = commands.get_handle(self, handle_id) else {
+            log::error!("The sprite sheet {} isn't loaded", self.texture_path.as_str());
+            return;
+        };
+
+        let mut sprite = TextureAtlasSprite::new(self.init_sprite);
+        sprite.anchor = self.anchor.into();
 
         commands.insert(SpriteSheetBundle {
-            sprite: TextureAtlasSprite::new(self.init_sprite),
+            sprite,
             texture_atlas: atlas_handle.clone(),
             ..default()
         });
     }
 
     fn prepare(&self, world: &mut World, prototype: &dyn Prototypical, data: &mut ProtoData) {
-        let asset_server = world.resource::<AssetServer>();
-        let image: Handle<Image> = asset_server.load(self.texture_path.as_str());
-
-        let mut atlasses = world.resource_mut::<Assets<TextureAtlas>>();
-
-        let atlas = TextureAtlas::from_grid(
-            image,
-            Vec2::splat(self.tile_size as f32),
-            self.sprite_width,
-            self.sprite_height,
-            None,
-            None,
-        );
-        let atlas_handle = atlasses.add(atlas);
+        let layout = match self.layout() {
+            Ok(layout) => layout,
+            Err(e) => {
+                log::error!("Invalid sprite sheet {}: {e}", self.texture_path.as_str());
+                return;
+            }
+        };
+
+        let key = self.cache_key(&layout);
+        let cached = world
+            .get_resource_or_insert_with(SpriteSheetCache::default)
+            .atlases
+            .get(&key)
+            .cloned();
+
+        let atlas_handle = match cached {
+            Some(atlas_handle) => atlas_handle,
+            None => {
+                let image: Handle<Image> = world.resource::<AssetServer>().load(self.texture_path.as_str());
+                let atlas_handle = world
+                    .resource_mut::<Assets<TextureAtlas>>()
+                    .add(layout.atlas(image));
+                world
+                    .resource_mut::<SpriteSheetCache>()
+                    .atlases
+                    .insert(key, atlas_handle.clone());
+                atlas_handle
+            }
+        };
 
         data.insert_handle(prototype, self, atlas_handle.clone());
 
@@ -0,0 +1,330 @@
+// Checks prototypes, after their `extends` are resolved, for data that would break
+// spawning or animating them. Component types are checked by deserializing the
+// components, which needs the game's registered types, so that's left to the caller.
+//
+// Part of the library, so prototyp-check validates prototypes the same way.
+
+use std::{collections::HashSet, fmt, path::Path};
+
+use serde_yaml::Value;
+
+// Animations are laid out for these many directions, see `update_character_animation`.
+const DIRECTIONS: u64 = 8;
+
+// A problem with the field at `field`, such as `components[2].value.kind`.
+pub struct Problem {
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+struct Validator<'a> {
+    prototypes: &'a HashSet<String>,
+    assets_dir: &'a Path,
+    problems: Vec<Problem>,
+}
+
+impl Validator<'_> {
+    fn problem(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.problems.push(Problem {
+            field: field.into(),
+            message: message.into(),
+        });
+    }
+
+    // A non-negative integer at `key` of `value`, reporting it if it's missing.
+    fn uint(&mut self, value: &Value, field: &str, key: &str) -> Option<u64> {
+        let n = value.get(key).and_then(|n| n.as_u64());
+        if n.is_none() {
+            self.problem(format!("{field}.{key}"), "Expected a non-negative integer");
+        }
+        n
+    }
+
+    // Returns the number of frames of the sprite sheet.
+    fn sprite_sheet(&mut self, value: &Value, field: &str) -> Option<u64> {
+        match value.get("texture_path").and_then(|path| path.as_str()) {
+            Some(path) if !self.assets_dir.join(path).exists() => {
+                self.problem(format!("{field}.texture_path"), format!("{path} doesn't exist"));
+            }
+            Some(_) => {}
+            None => self.problem(format!("{field}.texture_path"), "Expected the path of an image"),
+        }
+
+        let init_sprite = self.uint(value, field, "init_sprite");
+        let frames = match value.get("frames").and_then(|frames| frames.as_str()) {
+            Some(frames) => self.frame_map(frames, field)?,
+            None => self.grid(value, field)?,
+        };
+
+        if frames == 0 {
+            self.problem(field, "The sprite sheet has no frames");
+            return None;
+        }
+
+        if let Some(init_sprite) = init_sprite {
+            if init_sprite >= frames {
+                self.problem(
+                    format!("{field}.init_sprite"),
+                    format!("Frame {init_sprite} is outside of the sprite sheet's {frames} frames"),
+                );
+            }
+        }
+
+        Some(frames)
+    }
+
+    // Returns the number of frames of a sprite sheet laid out in a grid.
+    fn grid(&mut self, value: &Value, field: &str) -> Option<u64> {
+        match (value.get("tile_size"), value.get("frame_size")) {
+            (Some(_), _) => {
+                if self.uint(value, field, "tile_size") == Some(0) {
+                    self.problem(format!("{field}.tile_size"), "Must be greater than 0");
+                }
+            }
+            (None, Some(size)) => {
+                let sizes: Vec<Option<f64>> = size.as_sequence().into_iter().flatten().map(|v| v.as_f64()).collect();
+                if sizes.len() != 2 || !sizes.iter().all(|size| size.map_or(false, |size| size > 0.0)) {
+                    self.problem(format!("{field}.frame_size"), "Expected two sizes greater than 0");
+                }
+            }
+            (None, None) => self.problem(field, "Expected a tile_size, frame_size or frames"),
+        }
+
+        let width = self.uint(value, field, "sprite_width");
+        let height = self.uint(value, field, "sprite_height");
+        Some(width? * height?)
+    }
+
+    // Returns the number of frames of a JSON frame map. JSON is read as YAML.
+    fn frame_map(&mut self, path: &str, field: &str) -> Option<u64> {
+        let field = format!("{field}.frames");
+        let frame_map: Value = match std::fs::read_to_string(self.assets_dir.join(path))
+            .map_err(|e| e.to_string())
+            .and_then(|json| serde_yaml::from_str(&json).map_err(|e| e.to_string()))
+        {
+            Ok(frame_map) => frame_map,
+            Err(e) => {
+                self.problem(field, format!("Could not load {path}: {e}"));
+                return None;
+            }
+        };
+
+        let frames = match frame_map.get("frames") {
+            Some(Value::Sequence(frames)) => frames.len(),
+            Some(Value::Mapping(frames)) => frames.len(),
+            _ => {
+                self.problem(field, format!("{path} has no list or mapping of frames"));
+                return None;
+            }
+        };
+        if frame_map.get("meta").and_then(|meta| meta.get("size")).is_none() {
+            self.problem(field, format!("{path} is missing meta.size"));
+        }
+
+        Some(frames as u64)
+    }
+
+    fn animations(&mut self, value: &Value, field: &str, sheet_frames: Option<u64>) {
+        let Some(animations) = value.get("animations").and_then(|a| a.as_mapping()) else {
+            self.problem(format!("{field}.animations"), "Expected a mapping of animation states");
+            return;
+        };
+
+        // Characters start out idle.
+        if animations.get(&Value::from("Idle")).is_none() {
+            self.problem(format!("{field}.animations"), "Missing the Idle animation");
+        }
+
+        for (state, animation) in animations {
+            let state = state.as_str().unwrap_or_default();
+            let field = format!("{field}.animations.{state}");
+
+            let frame_cnt = self.uint(animation, &field, "frame_cnt");
+            let first_frame = self.uint(animation, &field, "first_frame_idx");
+            let dir_offset = self.uint(animation, &field, "dir_offset");
+
+            match animation.get("fps").and_then(|fps| fps.as_f64()) {
+                Some(fps) if fps > 0.0 => {}
+                _ => self.problem(format!("{field}.fps"), "Expected a number greater than 0"),
+            }
+
+            let (Some(frame_cnt), Some(first_frame), Some(dir_offset)) = (frame_cnt, first_frame, dir_offset) else {
+                continue;
+            };
+
+            if frame_cnt == 0 {
+                self.problem(format!("{field}.frame_cnt"), "Must be greater than 0");
+                continue;
+            }
+
+            // Frame indices are stored as u8.
+            let last_frame = first_frame + (DIRECTIONS - 1) * dir_offset + frame_cnt - 1;
+            if last_frame > u8::MAX as u64 {
+                self.problem(&field, format!("Frame {last_frame} exceeds the limit of {}", u8::MAX));
+            } else if let Some(sheet_frames) = sheet_frames {
+                if last_frame >= sheet_frames {
+                    self.problem(
+                        &field,
+                        format!("Frame {last_frame} is outside of the sprite sheet's {sheet_frames} frames"),
+                    );
+                }
+            }
+        }
+    }
+
+    fn collider_shape(&mut self, shape: &Value, field: &str, in_compound: bool) {
+        let shape_type = shape.get("type").and_then(|t| t.as_str()).unwrap_or_default();
+        let value = shape.get("value").unwrap_or(&Value::Null);
+        let field = format!("{field}.value");
+
+        let sizes: Vec<Option<f64>> = match value {
+            Value::Sequence(values) => values.iter().map(|v| v.as_f64()).collect(),
+            value => vec![value.as_f64()],
+        };
+        let positive = |sizes: &[Option<f64>], count: usize| {
+            sizes.len() == count && sizes.iter().all(|size| size.map_or(false, |size| size > 0.0))
+        };
+
+        match shape_type {
+            "Capsule" | "CapsuleX" | "Cuboid" if !positive(&sizes, 2) => {
+                self.problem(field, "Expected two sizes greater than 0");
+            }
+            "Circle" if !positive(&sizes, 1) => {
+                self.problem(field, "Expected a radius greater than 0");
+            }
+            "ConvexPolygon" if value.as_sequence().map_or(true, |points| points.len() < 3) => {
+                self.problem(field, "Expected at least 3 points");
+            }
+            "Compound" if in_compound => self.problem(field, "Compound shapes can't be nested"),
+            "Compound" => {
+                for (i, part) in value.as_sequence().into_iter().flatten().enumerate() {
+                    let shape = part.get("shape").unwrap_or(&Value::Null);
+                    self.collider_shape(shape, &format!("{field}[{i}].shape"), true);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn collision_groups(&mut self, groups: &Value, field: &str) {
+        for key in ["memberships", "filters"] {
+            for group in groups.get(key).and_then(|g| g.as_sequence()).into_iter().flatten() {
+                if !group.as_u64().map_or(false, |group| (1..=32).contains(&group)) {
+                    self.problem(format!("{field}.{key}"), format!("Collision group {group:?} isn't between 1 and 32"));
+                }
+            }
+        }
+    }
+
+    fn children(&mut self, children: &Value, field: &str) {
+        let Some(children) = children.as_sequence() else {
+            self.problem(field, "Expected a list of children");
+            return;
+        };
+
+        for (i, child) in children.iter().enumerate() {
+            let field = format!("{field}[{i}]");
+            match child.get("prototype").and_then(|p| p.as_str()) {
+                Some(prototype) if !self.prototypes.contains(prototype) => {
+                    self.problem(format!("{field}.prototype"), format!("There's no prototype {prototype}"));
+                }
+                Some(_) => {}
+                None => self.problem(format!("{field}.prototype"), "Expected the name of a prototype"),
+            }
+
+            if let Some(grandchildren) = child.get("children") {
+                self.children(grandchildren, &format!("{field}.children"));
+            }
+        }
+    }
+}
+
+// Validates a prototype. `prototypes` holds the names of all prototypes
+// and `assets_dir` is where asset paths are relative to.
+pub fn validate(prototype: &Value, prototypes: &HashSet<String>, assets_dir: &Path) -> Vec<Problem> {
+    let mut validator = Validator {
+        prototypes,
+        assets_dir,
+        problems: Vec::new(),
+    };
+
+    if prototype.get("name").and_then(|name| name.as_str()).is_none() {
+        validator.problem("name", "Expected the name of the prototype");
+    }
+
+    let components: &[Value] = match prototype.get("components") {
+        Some(Value::Sequence(components)) => components.as_slice(),
+        None | Some(Value::Null) => &[],
+        Some(_) => {
+            validator.problem("components", "Expected a list of components");
+            &[]
+        }
+    };
+
+    let mut types = HashSet::new();
+    let mut sheet_frames = None;
+    let mut animations = None;
+    for (i, component) in components.iter().enumerate() {
+        let field = format!("components[{i}]");
+        let Some(component_type) = component.get("type").and_then(|t| t.as_str()) else {
+            validator.problem(format!("{field}.type"), "Expected the type of the component");
+            continue;
+        };
+
+        if !types.insert(component_type) {
+            validator.problem(format!("{field}.type"), format!("{component_type} is listed more than once"));
+        }
+
+        let value = component.get("value").unwrap_or(&Value::Null);
+        let field = format!("{field}.value");
+        match component_type {
+            "SpriteSheetBundleDef" => sheet_frames = validator.sprite_sheet(value, &field),
+            // Checked once the sprite sheet is known.
+            "EntityAnimationData" => animations = Some((value, field)),
+            "ColliderDef" => {
+                let shape = value.get("shape").unwrap_or(&Value::Null);
+                validator.collider_shape(shape, &format!("{field}.shape"), false);
+                for key in ["collision_groups", "solver_groups"] {
+                    if let Some(groups) = value.get(key) {
+                        validator.collision_groups(groups, &format!("{field}.{key}"));
+                    }
+                }
+            }
+            "PhysicsDefault" => {
+                let has_controller = !matches!(value.get("controller"), None | Some(Value::Null));
+                let kind = value.get("kind").and_then(|kind| kind.as_str());
+                if has_controller && kind != Some("KinematicPositionBased") {
+                    validator.problem(
+                        format!("{field}.controller"),
+                        "A character controller needs a KinematicPositionBased body",
+                    );
+                }
+            }
+            "AI" => {
+                if value.get("kind").and_then(|kind| kind.as_str()) == Some("None") {
+                    validator.problem(format!("{field}.kind"), "None isn't a valid AI kind");
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if let Some((value, field)) = animations {
+        if !types.contains("SpriteSheetBundleDef") {
+            validator.problem(&field, "Animations need a SpriteSheetBundleDef");
+        }
+        validator.animations(value, &field, sheet_frames);
+    }
+
+    if let Some(children) = prototype.get("children") {
+        validator.children(children, "children");
+    }
+
+    validator.problems
+}
@@ -1,11 +1,19 @@
 use std::{collections::HashMap, path::{Path, PathBuf}};
 
 use bevy::{prelude::*, math::Vec3A};
-use tiled::PropertyValue;
+use tiled::{Properties, PropertyValue};
+
+use crate::{components::{AnimationState, Direction, AI}, dialogue::Dialogue, tiled::TiledObject};
 
 #[derive(Resource)]
 pub struct UiSettings {
     pub show_debug_window: bool,
+    pub show_nav_grid: bool,
+    // Replaces the map with a generated one on the next frame.
+    pub generate_map: bool,
+    // Writes the map to a TMX file on the next frame.
+    pub export_map: bool,
+    pub show_snapshot_window: bool,
 }
 
 #[derive(Resource)]
@@ -19,20 +27,56 @@ impl Default for CursorPos {
 
 #[derive(Resource, Default)]
 pub struct TilesProperties {
-    // Map TilesetId -> (Map TileId -> (Map PropName -> PropValue))
-    pub props: Vec<Vec<HashMap<String, PropertyValue>>>,
+    // Map entity -> (Map TilesetId -> (Map TileId -> (Map PropName -> PropValue)))
+    pub props: HashMap<Entity, Vec<Vec<HashMap<String, PropertyValue>>>>,
+}
+
+// The logic layers of each loaded map, by map entity.
+#[derive(Resource, Default)]
+pub struct LogicLayers {
+    pub maps: HashMap<Entity, MapLogicLayers>,
+}
+
+// Logic layers are stored per the name of the layer they describe.
+// Each holds the logic tile id (if any) for every map cell in Tiled's row order,
+// i.e row 0 is the top row of the map.
+#[derive(Default, Debug)]
+pub struct MapLogicLayers {
+    pub width: u32,
+    pub height: u32,
+    pub layers: HashMap<String, Vec<Option<u32>>>,
+}
+
+impl MapLogicLayers {
+    // The value of the logic layer describing `layer` at the given cell.
+    // `y` counts rows from the bottom of the map, like bevy_ecs_tilemap does.
+    pub fn value(&self, layer: &str, x: u32, y: u32) -> Option<u32> {
+        let values = self.layers.get(layer)?;
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        let tiled_y = (self.height - 1) - y;
+        values[(tiled_y * self.width + x) as usize]
+    }
 }
 
 #[derive(Debug)]
 pub struct SignData {
+    // The id of the sign's Tiled object, None for signs added at runtime.
+    pub object_id: Option<u32>,
+    // The Tiled class of the sign's object.
+    pub class: String,
     pub x: f32,
     pub y: f32,
     pub id: u32,
+    pub properties: Properties,
 }
 
+// The signs of each loaded map, by map entity.
 #[derive(Resource, Default)]
 pub struct SignsPool {
-    pub signs: Vec<SignData>,
+    pub signs: HashMap<Entity, Vec<SignData>>,
 }
 
 pub struct ObjectId(usize);
@@ -47,10 +91,25 @@ pub struct Object {
     kind: ObjectKind,
 }
 
+// What an NPC was doing when its chunk got unloaded.
+// Restored when the NPC is spawned again.
+#[derive(Default, Debug)]
+pub struct NpcState {
+    pub direction: Option<Direction>,
+    pub animation_state: Option<AnimationState>,
+    pub ai: Option<AI>,
+    pub dialogue: Option<Dialogue>,
+}
+
 #[derive(Debug)]
 pub struct NpcData {
     pub name: String,
+    // The Tiled class of the NPC's object.
+    pub class: String,
     pub pos: Vec3,
+    pub properties: Properties,
+    pub state: NpcState,
+    pub object: TiledObject,
 }
 
 #[derive(Resource, Default, Debug)]
@@ -58,6 +117,28 @@ pub struct NpcPool {
     pub npcs: Vec<NpcData>
 }
 
+impl NpcPool {
+    // Replaces the NPCs waiting to be spawned for `map`, e.g. when it's reloaded.
+    pub fn set_map_npcs(&mut self, map: Entity, npcs: Vec<NpcData>) {
+        self.npcs.retain(|npc| npc.object.map != map);
+        self.npcs.extend(npcs);
+    }
+}
+
+// NPCs of chunks which aren't loaded.
+#[derive(Resource, Default, Debug)]
+pub struct StashedNpcs {
+    pub npcs: Vec<NpcData>
+}
+
+impl StashedNpcs {
+    // Replaces the stashed NPCs of `map`, e.g. when it's reloaded.
+    pub fn set_map_npcs(&mut self, map: Entity, npcs: Vec<NpcData>) {
+        self.npcs.retain(|npc| npc.object.map != map);
+        self.npcs.extend(npcs);
+    }
+}
+
 // TODO:
 // - varaible can either be primitive type(Int, Str, Float...)
 //   or custom type(probably we'll need traits here)
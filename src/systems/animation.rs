@@ -10,7 +10,9 @@ pub fn update_character_animation(
 ) {
     // TODO: all of this should be from a config
     for (mut animation, state, dir, anim_data) in changed_animation_q.iter_mut() {
-        let anim_data = &anim_data.animations[state];
+        let Some(anim_data) = anim_data.animations.get(state) else {
+            continue;
+        };
         let mut offset = (anim_data.first_frame_idx, anim_data.frame_cnt);
 
         offset.0 += match dir {
@@ -35,11 +37,52 @@ pub fn update_character_animation(
 
 pub fn animate(mut anim_q: Query<(&mut Animation, &mut TextureAtlasSprite)>, time: Res<Time>) {
     for (mut anim, mut sprite) in anim_q.iter_mut() {
+        if anim.frames.is_empty() {
+            continue;
+        }
+
         anim.timer.tick(time.delta());
         if anim.first_frame || anim.timer.finished() {
             anim.frame_idx = (anim.frame_idx + 1) % anim.frames.len() as u8;
-            *sprite = TextureAtlasSprite::new(anim.frames[anim.frame_idx as usize] as usize);
+            sprite.index = anim.frames[anim.frame_idx as usize] as usize;
             anim.first_frame = false;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::sprite::Anchor;
+
+    use super::*;
+
+    #[test]
+    fn animated_sprites_keep_their_anchor() {
+        let mut app = App::new();
+        app.insert_resource(Time::default()).add_system(animate);
+
+        let anchor = Vec2::new(0.0, -0.25);
+        let entity = app
+            .world
+            .spawn((
+                Animation {
+                    timer: Timer::from_seconds(1.0, TimerMode::Repeating),
+                    frames: vec![3, 4],
+                    frame_idx: 0,
+                    first_frame: true,
+                },
+                TextureAtlasSprite {
+                    anchor: Anchor::Custom(anchor),
+                    flip_x: true,
+                    ..default()
+                },
+            ))
+            .id();
+        app.update();
+
+        let sprite = app.world.get::<TextureAtlasSprite>(entity).unwrap();
+        assert_eq!(sprite.index, 4);
+        assert_eq!(sprite.anchor.as_vec(), anchor);
+        assert!(sprite.flip_x);
+    }
+}
@@ -2,7 +2,7 @@ use bevy::prelude::*;
 use bevy_rapier2d::{prelude::*, rapier::prelude::CollisionEventFlags};
 use serde::{Serialize, Deserialize};
 
-use crate::{components::{Player, NPC, NPCDialogMarker, DialogueEntityWrapper, AI, AIKind, InNpcReach, HintEntityWrapper, Empty, InDialogueWith}, systems::text};
+use crate::{components::{Controlled, NPC, NPCDialogMarker, DialogueEntityWrapper, AI, AIKind, InNpcReach, HintEntityWrapper, Empty, InDialogueWith}, systems::text};
 
 use super::text::{spawn_text, TextValue, TextBuilder};
 
@@ -33,14 +33,16 @@ pub fn handle_player_npc_collision(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     mut collision_events: EventReader<CollisionEvent>,
-    player_q: Query<Entity, With<Player>>,
+    player_q: Query<Entity, With<Controlled>>,
     npc_q: Query<(&NPC, &AI)>,
     parent_q: Query<&Parent>,
     dialog_q: Query<&DialogueEntityWrapper>,
     in_reach_q: Query<&InNpcReach>,
     hint_q: Query<&HintEntityWrapper>,
 ) {
-    let player = player_q.get_single().unwrap();
+    let Ok(player) = player_q.get_single() else {
+        return;
+    };
 
     for collision_event in collision_events.iter() {
         match *collision_event {
@@ -56,6 +58,7 @@ pub fn handle_player_npc_collision(
                 // Check if collider belongs to player
                 if e == player {
                     is_player = true;
+                    continue;
                 }
 
                 // Check if collider belongs to npc
@@ -64,7 +67,11 @@ pub fn handle_player_npc_collision(
                 }
 
                 // Check if collider is child of npc (f.e sensor)
+                // A controlled NPC doesn't talk to itself.
                 if let Ok(p) = parent_q.get(e) {
+                    if p.get() == player {
+                        continue;
+                    }
                     if let Ok(entt) = npc_q.get(p.get()) {
                         npc = Some((entt.0, entt.1, p.get()));
                     }
@@ -89,6 +96,7 @@ pub fn handle_player_npc_collision(
                 let diag_entt = DialogueEntityWrapper(diag_entt);
                 commands.entity(player).insert(diag_entt);
             },
+            AIKind::Follow => {},
             AIKind::Talking => {
                 commands.entity(player).insert(InNpcReach(entt.2));
                 if !hint_q.contains(player) {
@@ -0,0 +1,71 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::Velocity;
+
+use crate::components::{
+    AIKind, Controllable, Controlled, DialogueEntityWrapper, HintEntityWrapper, InNpcReach, AI,
+};
+
+// Tab hands control to the next controllable character. The released character
+// keeps its own AI, or follows the newly controlled one if it has none.
+pub fn switch_controlled(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    controllable_q: Query<Entity, With<Controllable>>,
+    controlled_q: Query<Entity, With<Controlled>>,
+    ai_q: Query<&AI>,
+    hint_q: Query<&HintEntityWrapper>,
+    dialog_q: Query<&DialogueEntityWrapper>,
+    mut velocity_q: Query<&mut Velocity>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::Tab) {
+        return;
+    }
+
+    let mut controllable: Vec<Entity> = controllable_q.iter().collect();
+    controllable.sort();
+
+    let current = controlled_q.get_single().ok();
+    let next = match current.and_then(|c| controllable.iter().position(|e| *e == c)) {
+        Some(i) => controllable[(i + 1) % controllable.len()],
+        None => match controllable.first() {
+            Some(e) => *e,
+            None => return,
+        },
+    };
+
+    if current == Some(next) {
+        return;
+    }
+
+    if let Some(released) = current {
+        // Prompts and dialogue boxes belong to the controlled character.
+        if let Ok(HintEntityWrapper(hint)) = hint_q.get(released) {
+            commands.entity(*hint).despawn_recursive();
+        }
+        if let Ok(DialogueEntityWrapper(dialog)) = dialog_q.get(released) {
+            commands.entity(*dialog).despawn_recursive();
+        }
+
+        commands
+            .entity(released)
+            .remove::<Controlled>()
+            .remove::<InNpcReach>()
+            .remove::<HintEntityWrapper>()
+            .remove::<DialogueEntityWrapper>();
+
+        if !ai_q.contains(released) {
+            commands.entity(released).insert(AI {
+                kind: AIKind::Follow,
+            });
+        }
+
+        if let Ok(mut velocity) = velocity_q.get_mut(released) {
+            velocity.linvel = Vec2::ZERO;
+        }
+    }
+
+    if let Ok(mut velocity) = velocity_q.get_mut(next) {
+        velocity.linvel = Vec2::ZERO;
+    }
+    commands.entity(next).insert(Controlled);
+}
@@ -1,3 +1,5 @@
+use std::path::Path;
+
 use bevy::{app::AppExit, log, math::Vec4Swizzles, prelude::*};
 use bevy_ecs_tilemap::prelude::*;
 use bevy_egui::{egui, EguiContext};
@@ -5,12 +7,23 @@ use bevy_rapier2d::prelude::Velocity;
 
 use crate::systems::helpers::window_pos_in_world;
 use crate::{
-    components::{MainCamera, Player},
-    resources::{CursorPos, UiSettings},
+    components::{Controlled, MainCamera, MapNpc, NavGridOverlayMarker, Sign},
+    prototypes::{snapshot::SnapshotPrototype, PrototypeName},
+    resources::{CursorPos, NpcPool, SignsPool, StashedNpcs, UiSettings},
+    tiled::{
+        ExportMap, GeneratorRules, MapGenerator, NavGrid, TileQuery, TiledLayersStorage, TiledMap, TiledObject,
+    },
 };
 
+// Above the characters and most of the map.
+const NAV_GRID_OVERLAY_Z: f32 = 150.0;
+
+const RANDOM_MAP_RULES: &str = "assets/map/random_tileset.rules.yaml";
+
+const EXPORTED_MAP: &str = "assets/map/export.tmx";
+
 pub fn debug_input(
-    mut player_q: Query<&mut TextureAtlasSprite, With<Player>>,
+    mut player_q: Query<&mut TextureAtlasSprite, With<Controlled>>,
     keyboard_input: Res<Input<KeyCode>>,
     mut exit: EventWriter<AppExit>,
 ) {
@@ -28,7 +41,9 @@ pub fn debug_input(
     }
 
     if inc != 0 {
-        let mut s = player_q.single_mut();
+        let Ok(mut s) = player_q.get_single_mut() else {
+            return;
+        };
         s.index += inc;
         log::info!("Sprite idx: {}", s.index);
     }
@@ -62,8 +77,11 @@ pub fn draw_debug_ui(
         &TileStorage,
         &Transform,
     )>,
-    player_q: Query<(&Transform, &Velocity), With<Player>>,
+    player_q: Query<(&Transform, &Velocity), With<Controlled>>,
     camera_q: Query<(&Transform, &OrthographicProjection), With<MainCamera>>,
+    tile_query: TileQuery,
+    layer_storage_q: Query<&TiledLayersStorage>,
+    mut layer_visibility_q: Query<(&Name, &mut Visibility)>,
 ) {
     let mut player_pos = Vec3::default();
     let mut tile_pos = TilePos::default();
@@ -97,6 +115,46 @@ pub fn draw_debug_ui(
                     ui_settings.show_debug_window = !ui_settings.show_debug_window;
                     ui.close_menu();
                 }
+
+                if ui.button("Navigation Grid").clicked() {
+                    ui_settings.show_nav_grid = !ui_settings.show_nav_grid;
+                    ui.close_menu();
+                }
+            });
+
+            egui::menu::menu_button(ui, "Map", |ui| {
+                if ui.button("Generate Random Map").clicked() {
+                    ui_settings.generate_map = true;
+                    ui.close_menu();
+                }
+
+                if ui.button("Export Map").clicked() {
+                    ui_settings.export_map = true;
+                    ui.close_menu();
+                }
+            });
+
+            egui::menu::menu_button(ui, "Prototypes", |ui| {
+                if ui.button("Snapshot Entity").clicked() {
+                    ui_settings.show_snapshot_window = !ui_settings.show_snapshot_window;
+                    ui.close_menu();
+                }
+            });
+
+            egui::menu::menu_button(ui, "Layers", |ui| {
+                for layer_storage in layer_storage_q.iter() {
+                    let mut layers: Vec<_> = layer_storage.storage.iter().collect();
+                    layers.sort_by_key(|(index, _)| **index);
+
+                    for (_, layer_entity) in layers {
+                        if let Ok((name, mut visibility)) = layer_visibility_q.get_mut(*layer_entity) {
+                            let mut is_visible = visibility.is_visible;
+                            if ui.checkbox(&mut is_visible, name.as_str()).changed() {
+                                visibility.is_visible = is_visible;
+                            }
+                        }
+                    }
+                }
             });
         });
     });
@@ -109,9 +167,238 @@ pub fn draw_debug_ui(
             ui.label(format!("Camera transform: {:?}", cam_t));
             ui.label(format!("Camera zoom: {:?}", 1.0 / ortho.scale));
 
+            ui.separator();
+            for tile in tile_query.tiles_at(player_pos.truncate()) {
+                let logic = tile_query.logic_value(tile.map, tile.layer, tile.pos);
+                ui.label(format!(
+                    "Layer {}: tile {} at {:?}, logic: {:?}, properties: {:?}",
+                    tile.layer, tile.tile_id, tile.pos, logic, tile.properties
+                ));
+            }
+
             if ui.button("Close").clicked() {
                 ui_settings.show_debug_window = false;
             }
         });
     }
 }
+
+// Tints the blocked cells of the navigation grid red and the ones that are costly to walk yellow.
+pub fn draw_nav_grid_overlay(
+    mut commands: Commands,
+    ui_settings: Res<UiSettings>,
+    nav_grid: Res<NavGrid>,
+    overlay_q: Query<Entity, With<NavGridOverlayMarker>>,
+    mut shown: Local<bool>,
+) {
+    if *shown == ui_settings.show_nav_grid && !nav_grid.is_changed() {
+        return;
+    }
+    *shown = ui_settings.show_nav_grid;
+
+    for overlay in overlay_q.iter() {
+        commands.entity(overlay).despawn_recursive();
+    }
+
+    if !ui_settings.show_nav_grid {
+        return;
+    }
+
+    commands
+        .spawn(SpatialBundle::from_transform(Transform::from_xyz(0.0, 0.0, NAV_GRID_OVERLAY_Z)))
+        .insert(NavGridOverlayMarker)
+        .insert(Name::new("Navigation Grid"))
+        .with_children(|parent| {
+            for x in 0..nav_grid.width {
+                for y in 0..nav_grid.height {
+                    let pos = TilePos { x, y };
+                    let color = match nav_grid.cost(pos) {
+                        None => Color::rgba(1.0, 0.0, 0.0, 0.35),
+                        Some(cost) if cost > 1.0 => Color::rgba(1.0, 1.0, 0.0, 0.35),
+                        _ => continue,
+                    };
+
+                    parent.spawn(SpriteBundle {
+                        sprite: Sprite {
+                            color,
+                            custom_size: Some(nav_grid.cell_size),
+                            ..default()
+                        },
+                        transform: Transform::from_translation(nav_grid.cell_center(pos).extend(0.0)),
+                        ..default()
+                    });
+                }
+            }
+        });
+}
+
+// Replaces the map closest to the player with one generated from the rules of
+// random_tileset.png. The NPCs and signs of the old map are removed right away.
+pub fn generate_random_map(
+    mut commands: Commands,
+    mut ui_settings: ResMut<UiSettings>,
+    asset_server: Res<AssetServer>,
+    mut maps: ResMut<Assets<TiledMap>>,
+    mut map_q: Query<(Entity, &mut Handle<TiledMap>, &Transform)>,
+    player_q: Query<&Transform, With<Controlled>>,
+    npc_q: Query<(Entity, &TiledObject), With<MapNpc>>,
+    sign_q: Query<(Entity, &Sign)>,
+    mut signs_res: ResMut<SignsPool>,
+    mut npc_res: ResMut<NpcPool>,
+    mut stashed_npcs: ResMut<StashedNpcs>,
+) {
+    if !ui_settings.generate_map {
+        return;
+    }
+    ui_settings.generate_map = false;
+
+    let rules = match GeneratorRules::load(Path::new(RANDOM_MAP_RULES)) {
+        Ok(rules) => rules,
+        Err(e) => {
+            log::error!("{e}");
+            return;
+        }
+    };
+
+    let generator = MapGenerator {
+        width: 30,
+        height: 30,
+        seed: rand::random(),
+        npcs: rules.npcs.clone(),
+        signs: rules.signs.clone(),
+    };
+    log::info!("Generating map with seed {}", generator.seed);
+
+    let tiled_map = match generator
+        .generate(&rules)
+        .map_err(|e| e.to_string())
+        .and_then(|generated| generated.to_tiled_map(&asset_server).map_err(|e| e.to_string()))
+    {
+        Ok(tiled_map) => tiled_map,
+        Err(e) => {
+            log::error!("Could not generate map: {e}");
+            return;
+        }
+    };
+
+    // Maps are centered around their transform.
+    let player_pos = player_q.get_single().map_or(Vec3::ZERO, |transform| transform.translation);
+    let closest = map_q
+        .iter_mut()
+        .min_by(|(_, _, a), (_, _, b)| {
+            let a = a.translation.truncate().distance_squared(player_pos.truncate());
+            let b = b.translation.truncate().distance_squared(player_pos.truncate());
+            a.total_cmp(&b)
+        });
+
+    // The new handle is picked up by `process_loaded_maps` once the asset is added.
+    if let Some((map_entity, mut map_handle, _)) = closest {
+        for (npc_entity, tiled_object) in npc_q.iter() {
+            if tiled_object.map == map_entity {
+                commands.entity(npc_entity).despawn_recursive();
+            }
+        }
+        for (sign_entity, sign) in sign_q.iter() {
+            if sign.map == map_entity {
+                commands.entity(sign_entity).despawn_recursive();
+            }
+        }
+        signs_res.signs.remove(&map_entity);
+        npc_res.set_map_npcs(map_entity, Vec::new());
+        stashed_npcs.set_map_npcs(map_entity, Vec::new());
+
+        *map_handle = maps.add(tiled_map);
+    }
+}
+
+// Writes the current state of each loaded map next to its file, as assets/map/simple.export.tmx
+// for simple.tmx. Generated maps don't have a file and are written to assets/map/export.tmx.
+pub fn export_current_map(
+    mut ui_settings: ResMut<UiSettings>,
+    asset_server: Res<AssetServer>,
+    map_q: Query<(Entity, &Handle<TiledMap>)>,
+    mut export_events: EventWriter<ExportMap>,
+) {
+    if !ui_settings.export_map {
+        return;
+    }
+    ui_settings.export_map = false;
+
+    for (map, map_handle) in map_q.iter() {
+        let path = match asset_server.get_handle_path(map_handle) {
+            Some(asset_path) => Path::new("assets")
+                .join(asset_path.path())
+                .with_extension("export.tmx"),
+            None => EXPORTED_MAP.into(),
+        };
+        export_events.send(ExportMap { map, path });
+    }
+}
+
+// Lets an entity spawned from a prototype, tweaked in the inspector, be written back to
+// a prototype file. Starts out with the controlled character selected.
+pub fn draw_snapshot_window(
+    mut egui_ctx: ResMut<EguiContext>,
+    mut ui_settings: ResMut<UiSettings>,
+    prototype_q: Query<(Entity, &PrototypeName, Option<&Name>)>,
+    controlled_q: Query<Entity, With<Controlled>>,
+    mut snapshot_events: EventWriter<SnapshotPrototype>,
+    mut selected: Local<Option<Entity>>,
+    mut target: Local<String>,
+) {
+    if !ui_settings.show_snapshot_window {
+        return;
+    }
+
+    let selected_entity = selected
+        .filter(|entity| prototype_q.contains(*entity))
+        .or_else(|| controlled_q.iter().find(|entity| prototype_q.contains(*entity)));
+    if *selected != selected_entity {
+        *selected = selected_entity;
+        *target = selected_entity
+            .and_then(|entity| prototype_q.get(entity).ok())
+            .map(|(_, prototype, _)| prototype.0.clone())
+            .unwrap_or_default();
+    }
+
+    let label = |(entity, prototype, name): (Entity, &PrototypeName, Option<&Name>)| {
+        format!("{} ({}, {entity:?})", name.map_or("", Name::as_str), prototype.0)
+    };
+
+    let mut open = true;
+    egui::Window::new("Snapshot Entity").open(&mut open).show(egui_ctx.ctx_mut(), |ui| {
+        let selected_text = selected
+            .and_then(|entity| prototype_q.get(entity).ok())
+            .map(label)
+            .unwrap_or_default();
+
+        egui::ComboBox::from_label("Entity")
+            .selected_text(selected_text)
+            .show_ui(ui, |ui| {
+                for item in prototype_q.iter() {
+                    let (entity, prototype, _) = item;
+                    if ui.selectable_label(*selected == Some(entity), label(item)).clicked() {
+                        *selected = Some(entity);
+                        *target = prototype.0.clone();
+                    }
+                }
+            });
+
+        ui.horizontal(|ui| {
+            ui.label("Prototype");
+            ui.text_edit_singleline(&mut *target);
+        });
+        ui.label(format!("Writes assets/prototypes/{}.yaml", target.trim()));
+
+        let name = target.trim().to_string();
+        if let (Some(entity), false) = (*selected, name.is_empty()) {
+            if ui.button("Save").clicked() {
+                snapshot_events.send(SnapshotPrototype { entity, name });
+            }
+        }
+    });
+
+    if !open {
+        ui_settings.show_snapshot_window = false;
+    }
+}
@@ -1,6 +1,6 @@
 use bevy::prelude::*;
 
-use crate::{components::{InNpcReach, HintEntityWrapper, Player, DialogueEntityWrapper, Empty, NPC}, dialogue::{Dialogue, DialogueTree}, resources::VariablePool};
+use crate::{components::{InNpcReach, HintEntityWrapper, Controlled, DialogueEntityWrapper, Empty, NPC}, dialogue::{Dialogue, DialogueTree}, resources::VariablePool};
 
 use super::text;
 
@@ -8,8 +8,8 @@ use super::text;
 pub fn resolve_text(
     text: &str,
     suffix: Option<&str>,
-    player_q: &Query<&Name, With<Player>>,
-    npc_name_q: &Query<(&Name, &NPC), Without<Player>>,
+    player_q: &Query<&Name, With<Controlled>>,
+    npc_name_q: &Query<(&Name, &NPC), Without<Controlled>>,
     _variables: &Res<VariablePool>,
 ) -> String {
     let mut res = String::new();
@@ -73,12 +73,12 @@ pub fn resolve_dialogue(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     keyboard_input: Res<Input<KeyCode>>,
-    player_q: Query<(Entity, &InNpcReach), With<Player>>,
+    player_q: Query<(Entity, &InNpcReach), With<Controlled>>,
     hint_q: Query<&HintEntityWrapper>,
     mut dialogue_q: Query<&mut Dialogue>,
     text_q: Query<&DialogueEntityWrapper>,
-    player_name_q: Query<&Name, With<Player>>,
-    npc_name_q: Query<(&Name, &NPC), Without<Player>>,
+    player_name_q: Query<&Name, With<Controlled>>,
+    npc_name_q: Query<(&Name, &NPC), Without<Controlled>>,
     variables: Res<VariablePool>,
 ) {
     if let Ok((entt, diag_with)) = player_q.get_single() {
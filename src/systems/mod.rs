@@ -4,6 +4,7 @@ mod helpers;
 
 pub mod animation;
 pub mod collision;
+pub mod control;
 pub mod debug;
 pub mod movement;
 pub mod setup;
@@ -11,6 +12,7 @@ pub mod sign;
 pub mod text;
 pub mod npc;
 pub mod dialogue;
+pub mod ysort;
 
 #[derive(SystemLabel)]
 pub enum PrototypSystemLabel {
@@ -1,5 +1,5 @@
 use crate::{
-    components::{AnimationState, Direction, Player, AI, NPC, AIKind},
+    components::{AnimationState, Direction, Controlled, AI, AIKind},
     prototypes::npc::Speed,
 };
 use bevy::{
@@ -8,11 +8,11 @@ use bevy::{
     prelude::*,
     render::camera::Camera,
 };
-use bevy_rapier2d::prelude::{Velocity, KinematicCharacterControllerOutput};
+use bevy_rapier2d::prelude::{Velocity, KinematicCharacterController, KinematicCharacterControllerOutput};
 
 pub fn player_movement(
     keyboard_input: Res<Input<KeyCode>>,
-    mut player_query: Query<(&mut Velocity, &mut AnimationState, &mut Direction, &Speed), With<Player>>,
+    mut player_query: Query<(&mut Velocity, &mut AnimationState, &mut Direction, &Speed), With<Controlled>>,
 ) {
     let mut fast = 1.0;
     if keyboard_input.pressed(KeyCode::LShift) {
@@ -82,7 +82,7 @@ pub fn player_movement(
 pub fn camera_movement(
     time: Res<Time>,
     mut query: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
-    player_q: Query<&Transform, (With<Player>, Without<Camera>)>,
+    player_q: Query<&Transform, (With<Controlled>, Without<Camera>)>,
     keyboard_input: Res<Input<KeyCode>>,
 ) {
     for (mut camera_transform, mut ortho) in query.iter_mut() {
@@ -102,7 +102,9 @@ pub fn camera_movement(
             ortho.scale = 3.0;
         }
 
-        let player_transform = player_q.single();
+        let Ok(player_transform) = player_q.get_single() else {
+            continue;
+        };
         let z = camera_transform.translation.z;
 
         if (camera_transform.translation.xy() - player_transform.translation.xy()).length() < 1.0 {
@@ -149,11 +151,14 @@ fn quantize_dir(dir: Vec2) -> Direction {
 }
 
 pub fn ai_movement(
-    mut npc_q: Query<(Entity, &mut Velocity, &mut AnimationState, &mut Direction, &mut Transform, &AI), With<NPC>>,
+    mut npc_q: Query<(Entity, &mut Velocity, &mut AnimationState, &mut Direction, &mut Transform, &AI), Without<Controlled>>,
     speed_q: Query<&Speed>,
-    player_q: Query<&Transform, (With<Player>, Without<NPC>)>
+    player_q: Query<&Transform, With<Controlled>>
 ) {
-    let player_pos = player_q.single().translation;
+    let Ok(player_transform) = player_q.get_single() else {
+        return;
+    };
+    let player_pos = player_transform.translation;
 
     for (entity, mut velocity, mut state, mut direction, t, ai) in npc_q.iter_mut() {
         let mut new_state = AnimationState::Idle;
@@ -190,7 +195,22 @@ pub fn ai_movement(
                 velocity.linvel = Vec2::ZERO;
             }
         },
-        _ => { unreachable!() }
+        AIKind::Follow => {
+            if dir_to_player.length() > 64.0 {
+                let default_speed = Speed::default();
+                let speed = speed_q.get(entity).unwrap_or(&default_speed);
+
+                velocity.linvel = (-speed.0 * dir_to_player.normalize()).xy();
+                new_state = AnimationState::Walking;
+                new_dir = quantize_dir(velocity.linvel);
+            } else {
+                velocity.linvel = Vec2::ZERO;
+            }
+        },
+        // Stands still, e.g. a character released from control without an AI of its own.
+        AIKind::None => {
+            velocity.linvel = Vec2::ZERO;
+        },
         }
         
         // We need to check first, as animation system operates on
@@ -204,4 +224,15 @@ pub fn ai_movement(
             *direction = new_dir;
         }
     }
-}
\ No newline at end of file
+}
+
+// Characters moved by a kinematic character controller get their
+// velocity applied through it, so they don't pass through colliders.
+pub fn drive_character_controllers(
+    mut controller_q: Query<(&Velocity, &mut KinematicCharacterController)>,
+    time: Res<Time>,
+) {
+    for (velocity, mut controller) in controller_q.iter_mut() {
+        controller.translation = Some(velocity.linvel * time.delta_seconds());
+    }
+}
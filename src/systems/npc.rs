@@ -4,7 +4,7 @@ use bevy::prelude::*;
 use bevy_proto::prelude::ProtoData;
 use bevy_rapier2d::prelude::ActiveHooks;
 
-use crate::{resources::{NpcPool, NpcData}, prototypes::spawn_prototype, components::{AI, NPC, AIKind}, dialogue::Dialogue};
+use crate::{resources::{NpcPool, NpcData}, prototypes::{insert_overrides, overrides, spawn_prototype, PrototypeChildren, ResolvedPrototypes}, components::{AI, NPC, AIKind, MapNpc}, dialogue::Dialogue, tiled::InsertTiledClass};
 
 use super::collision::PhysicsFilterTag;
 
@@ -12,21 +12,56 @@ pub fn spawn_npcs(
     mut commands: Commands,
     mut npc_res: ResMut<NpcPool>,
     asset_server: Res<AssetServer>,
-    proto_data: Res<ProtoData>
+    proto_data: Res<ProtoData>,
+    prototype_children: Res<PrototypeChildren>,
+    resolved_prototypes: Res<ResolvedPrototypes>,
 ) {
     if !npc_res.is_changed() {
         return;
     }
 
-    for NpcData{ name, pos } in npc_res.npcs.iter() {
-        let id = spawn_prototype(&name, &mut commands, &asset_server, &proto_data);
+    for NpcData{ name, class, pos, properties, state, object } in npc_res.npcs.drain(..) {
+        let Some(id) = spawn_prototype(&name, &mut commands, &asset_server, &proto_data, &prototype_children) else {
+            continue;
+        };
         commands.entity(id)
             .insert(SpatialBundle::from_transform(Transform::from_xyz(pos.x, pos.y, pos.z)))
             .insert(ActiveHooks::FILTER_CONTACT_PAIRS)
             .insert(PhysicsFilterTag::Npc);
-    }
 
-    npc_res.npcs.clear()
+        // Variations of the prototype set up in Tiled.
+        insert_overrides(id, &name, &properties, &mut commands, &asset_server, &proto_data, &resolved_prototypes);
+        match properties.get(overrides::DIALOGUE) {
+            Some(tiled::PropertyValue::StringValue(file)) if state.dialogue.is_none() => {
+                let file = PathBuf::from("assets").join("dialogues").join(file);
+                log::info!("Loading dialogue {:?} for npc {}", file, name);
+                commands.entity(id).insert(Dialogue::new(file));
+            }
+            _ => {}
+        }
+
+        commands.add(InsertTiledClass {
+            entity: id,
+            class: class.clone(),
+            properties: properties.clone(),
+        });
+
+        // NPCs respawned along with their chunk continue where they left off.
+        if let Some(direction) = state.direction {
+            commands.entity(id).insert(direction);
+        }
+        if let Some(animation_state) = state.animation_state {
+            commands.entity(id).insert(animation_state);
+        }
+        if let Some(ai) = state.ai {
+            commands.entity(id).insert(ai);
+        }
+        if let Some(dialogue) = state.dialogue {
+            commands.entity(id).insert(dialogue);
+        }
+
+        commands.entity(id).insert(MapNpc { name, class, properties }).insert(object);
+    }
 }
 
 pub fn spawn_npc_dialogues(
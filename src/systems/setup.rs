@@ -2,17 +2,22 @@ use bevy::prelude::*;
 use bevy_proto::prelude::ProtoData;
 use bevy_rapier2d::prelude::ActiveHooks;
 
-use crate::components::MainCamera;
-use crate::prototypes::spawn_prototype;
+use crate::components::{Controlled, MainCamera};
+use crate::prototypes::{spawn_prototype, PrototypeChildren};
 use crate::tiled;
 
 use super::collision::PhysicsFilterTag;
 
+// The world's first map is simple.tmx, 960 pixels wide and high. Its top-left
+// corner is placed so it's centered on the origin, where the player spawns.
+const WORLD_ORIGIN: Vec3 = Vec3::new(-480.0, 480.0, 0.0);
+
 pub fn startup(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let map_handle: Handle<tiled::TiledMap> = asset_server.load("map/simple.tmx");
+    let world_handle: Handle<tiled::TiledWorld> = asset_server.load("map/simple.world");
 
-    commands.spawn(tiled::TiledMapBundle {
-        tiled_map: map_handle,
+    commands.spawn(tiled::TiledWorldBundle {
+        world: world_handle,
+        transform: Transform::from_translation(WORLD_ORIGIN),
         ..Default::default()
     });
 }
@@ -25,9 +30,13 @@ pub fn spawn_player(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     proto_data: Res<ProtoData>,
+    prototype_children: Res<PrototypeChildren>,
 ) {
-    let id = spawn_prototype("player", &mut commands, &asset_server, &proto_data);
+    let Some(id) = spawn_prototype("player", &mut commands, &asset_server, &proto_data, &prototype_children) else {
+        return;
+    };
     commands.entity(id)
         .insert(ActiveHooks::FILTER_CONTACT_PAIRS)
-        .insert(PhysicsFilterTag::Player);
+        .insert(PhysicsFilterTag::Player)
+        .insert(Controlled);
 }
@@ -2,19 +2,29 @@ use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 
 use crate::{
-    components::{EntityPair, DialogueEntityWrapper, MainCamera, Sign, SignTextMarker, Player},
+    components::{EntityPair, DialogueEntityWrapper, MainCamera, Sign, SignTextMarker, Controlled},
     resources::SignsPool,
     systems::text::{self, TextPosition, TextValue, TextBuilder},
+    tiled::InsertTiledClass,
 };
 
-pub fn add_sign_sensors(mut commands: Commands, signs_res: Res<SignsPool>) {
+pub fn add_sign_sensors(mut commands: Commands, signs_res: Res<SignsPool>, sign_q: Query<Entity, With<Sign>>) {
     if !signs_res.is_changed() {
         return;
     }
 
-    for (handle, sign) in signs_res.signs.iter().enumerate() {
-        commands
-            .spawn(Sign { handle })
+    // The sensors of every map are spawned again, as the signs of a reloaded map replace its old ones.
+    for sign_entt in sign_q.iter() {
+        commands.entity(sign_entt).despawn_recursive();
+    }
+
+    for (handle, sign, map) in signs_res
+        .signs
+        .iter()
+        .flat_map(|(map, signs)| signs.iter().enumerate().map(|(handle, sign)| (handle, sign, *map)))
+    {
+        let sign_entt = commands
+            .spawn(Sign { map, handle })
             .insert(TransformBundle::from(Transform::from_xyz(
                 sign.x, sign.y, 10.0,
             )))
@@ -24,7 +34,14 @@ pub fn add_sign_sensors(mut commands: Commands, signs_res: Res<SignsPool>) {
                     .insert(Sensor)
                     .insert(ActiveEvents::COLLISION_EVENTS)
                     .insert(TransformBundle::from(Transform::default()));
-            });
+            })
+            .id();
+
+        commands.add(InsertTiledClass {
+            entity: sign_entt,
+            class: sign.class.clone(),
+            properties: sign.properties.clone(),
+        });
     }
 }
 
@@ -39,14 +56,14 @@ pub fn handle_sign_collision(
     sensor_q: Query<&Parent, With<Sensor>>,
     parents_q: Query<&Parent>,
     sign_q: Query<&Sign>,
-    player_q: Query<Entity, With<Player>>,
+    player_q: Query<Entity, With<Controlled>>,
     entt_pairs_q: Query<(&EntityPair, &DialogueEntityWrapper)>,
     camera_q: Query<(&Camera, &GlobalTransform, &OrthographicProjection), With<MainCamera>>,
     signs_res: Res<SignsPool>,
     windows: Res<Windows>,
 ) {
     enum SignId {
-        Start(usize, Entity, Entity),
+        Start(Entity, usize, Entity, Entity),
         Stop(Entity, Entity),
 
         Invalid,
@@ -79,7 +96,7 @@ pub fn handle_sign_collision(
 
                 if other_entt == player_entt {
                     if let Ok(sign) = sign_q.get(sign_entt) {
-                        SignId::Start(sign.handle, *e1, *e2)
+                        SignId::Start(sign.map, sign.handle, *e1, *e2)
                     } else {
                         SignId::Invalid
                     }
@@ -91,8 +108,10 @@ pub fn handle_sign_collision(
         };
 
         match sign_id {
-            SignId::Start(handle, e1, e2) => {
-                let sign_data = &signs_res.signs[handle];
+            SignId::Start(map, handle, e1, e2) => {
+                let Some(sign_data) = signs_res.signs.get(&map).and_then(|signs| signs.get(handle)) else {
+                    continue;
+                };
                 let id = sign_data.id;
 
                 let (camera, camera_transform, ortho) = camera_q.single();
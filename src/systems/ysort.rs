@@ -0,0 +1,26 @@
+use bevy::prelude::*;
+
+use crate::components::YSort;
+
+// Y-sorted entities and map rows get a z in [MIN_Z, MAX_Z].
+// Map layers below (z < 10) and above (z >= 100) the characters are left as is.
+const MIN_Z: f32 = 10.0;
+const MAX_Z: f32 = 90.0;
+const Z_PER_PIXEL: f32 = 0.01;
+
+// Lower on the screen means closer to the viewer.
+pub fn sorted_z(feet_y: f32) -> f32 {
+    let mid_z = (MIN_Z + MAX_Z) / 2.0;
+    (mid_z - feet_y * Z_PER_PIXEL).clamp(MIN_Z, MAX_Z)
+}
+
+pub fn y_sort(mut sorted_q: Query<(&YSort, &mut Transform)>) {
+    for (y_sort, mut transform) in sorted_q.iter_mut() {
+        let z = sorted_z(transform.translation.y + y_sort.feet_offset);
+
+        // Avoid triggering change detection when nothing moved.
+        if transform.translation.z != z {
+            transform.translation.z = z;
+        }
+    }
+}
@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use tiled::PropertyValue;
+
+use crate::{
+    components::{AnimationState, Direction, MainCamera, MapNpc, AI},
+    dialogue::Dialogue,
+    resources::{NpcData, NpcPool, NpcState, StashedNpcs},
+};
+
+use super::{
+    despawn_with_tiles, edit::TileEdits, flattened_layers, map_top_left, object_layer, tile_layer,
+    TiledClassRegistry, TiledLayersStorage, TiledMap, TiledObject,
+};
+
+/// A rectangle of tiles in bevy_ecs_tilemap's coordinates,
+/// i.e `min` is the bottom-left tile of the region.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TileRegion {
+    pub min: UVec2,
+    pub size: UVec2,
+}
+
+impl TileRegion {
+    pub fn whole(map: &tiled::Map) -> Self {
+        Self {
+            min: UVec2::ZERO,
+            size: UVec2::new(map.width, map.height),
+        }
+    }
+
+    // The tiles of `chunk`, clipped to the map.
+    pub fn chunk(map: &tiled::Map, chunk: IVec2, chunk_size: UVec2) -> Self {
+        let min = (chunk.max(IVec2::ZERO).as_uvec2() * chunk_size).min(UVec2::new(map.width, map.height));
+        let max = (min + chunk_size).min(UVec2::new(map.width, map.height));
+
+        Self {
+            min,
+            size: max - min,
+        }
+    }
+
+    pub fn contains(&self, pos: UVec2) -> bool {
+        pos.cmpge(self.min).all() && pos.cmplt(self.min + self.size).all()
+    }
+
+    pub fn positions(&self) -> impl Iterator<Item = UVec2> {
+        let min = self.min;
+        let size = self.size;
+        (0..size.x).flat_map(move |x| (0..size.y).map(move |y| min + UVec2::new(x, y)))
+    }
+}
+
+/// Spawns the map in chunks around the `MainCamera` instead of all at once.
+///
+/// Image layers, signs and the logic layers are still loaded for the whole map.
+/// NPCs are despawned along with their chunk and respawned in the state they were left in.
+#[derive(Resource)]
+pub struct ChunkStreaming {
+    pub enabled: bool,
+    // Size of a chunk, in tiles.
+    pub chunk_size: UVec2,
+    // Chunks this many chunks away from the camera's chunk are loaded.
+    pub load_distance: u32,
+    // Loaded chunks are kept until they're this many chunks further away,
+    // so walking along a chunk border doesn't respawn the chunks over and over.
+    pub unload_margin: u32,
+}
+
+impl Default for ChunkStreaming {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            chunk_size: UVec2::new(16, 16),
+            load_distance: 2,
+            unload_margin: 1,
+        }
+    }
+}
+
+impl ChunkStreaming {
+    // The chunk containing `world_pos` on a map placed at `map_offset`. Chunk (0, 0) holds
+    // the bottom-left tile of the map. Layer offsets and parallax aren't taken into account.
+    pub fn chunk_at(&self, map: &tiled::Map, map_offset: Vec2, world_pos: Vec2) -> IVec2 {
+        let map_bottom_left = map_top_left(map) + map_offset
+            - Vec2::new(0.0, (map.height * map.tile_height) as f32);
+        let chunk_px = Vec2::new(
+            (self.chunk_size.x * map.tile_width) as f32,
+            (self.chunk_size.y * map.tile_height) as f32,
+        );
+
+        ((world_pos - map_bottom_left) / chunk_px).floor().as_ivec2()
+    }
+
+    // Number of chunks along each axis of the map.
+    pub fn chunk_count(&self, map: &tiled::Map) -> IVec2 {
+        let map_size = UVec2::new(map.width, map.height);
+        ((map_size + self.chunk_size - UVec2::ONE) / self.chunk_size).as_ivec2()
+    }
+}
+
+fn chunk_distance(a: IVec2, b: IVec2) -> u32 {
+    (a - b).abs().max_element() as u32
+}
+
+// The entities spawned for each loaded chunk of a map.
+#[derive(Component, Default)]
+pub struct MapChunks {
+    pub loaded: HashMap<IVec2, Vec<Entity>>,
+}
+
+// Spawns the tiles and objects of `region` on every layer as children of the layer entities.
+// Returns the spawned entities.
+pub(crate) fn spawn_region(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    tiled_map: &TiledMap,
+    map_entity: Entity,
+    map_offset: Vec2,
+    layer_storage: &TiledLayersStorage,
+    tile_edits: &TileEdits,
+    class_registry: &TiledClassRegistry,
+    region: TileRegion,
+) -> Vec<Entity> {
+    let mut spawned = Vec::new();
+    for (layer_index, flattened_layer) in flattened_layers(&tiled_map.map, map_offset).iter().enumerate() {
+        let Some(&layer_entity) = layer_storage.storage.get(&(layer_index as u32)) else {
+            continue;
+        };
+
+        let entities = match flattened_layer.layer.layer_type() {
+            tiled::LayerType::TileLayer(tiled::TileLayer::Finite(layer_data)) => {
+                // The TilemapBundle requires that all tile images come exclusively from a single
+                // tiled texture or from a Vec of independent per-tile images. Furthermore, all of
+                // the per-tile images must be the same size. Since Tiled allows tiles of mixed
+                // tilesets on each layer and allows differently-sized tile images in each tileset,
+                // this means we need to load each combination of tileset and layer separately.
+                let mut entities = Vec::new();
+                for tileset_index in 0..tiled_map.map.tilesets().len() {
+                    if let Some(PropertyValue::IntValue(ts_index)) =
+                        flattened_layer.properties.get("tileset_index")
+                    {
+                        if *ts_index as usize != tileset_index {
+                            continue;
+                        }
+                    }
+
+                    entities.extend(tile_layer::spawn_tile_layer(
+                        commands,
+                        asset_server,
+                        tiled_map,
+                        map_entity,
+                        flattened_layer,
+                        &layer_data,
+                        tile_edits.layer(&flattened_layer.layer.name),
+                        tileset_index,
+                        region,
+                    ));
+                }
+                entities
+            }
+            tiled::LayerType::ObjectLayer(obj_layer) => object_layer::spawn_objects(
+                commands,
+                asset_server,
+                &tiled_map.map,
+                map_entity,
+                flattened_layer,
+                &obj_layer,
+                class_registry,
+                region,
+            ),
+            // Image layers cover the whole map and aren't streamed.
+            _ => continue,
+        };
+
+        commands.entity(layer_entity).push_children(&entities);
+        spawned.extend(entities);
+    }
+
+    spawned
+}
+
+pub fn stream_chunks(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    settings: Res<ChunkStreaming>,
+    maps: Res<Assets<TiledMap>>,
+    class_registry: Res<TiledClassRegistry>,
+    camera_q: Query<&Transform, With<MainCamera>>,
+    mut map_query: Query<(
+        Entity,
+        &Handle<TiledMap>,
+        &Transform,
+        &TiledLayersStorage,
+        &TileEdits,
+        &mut MapChunks,
+    )>,
+    tile_storage_q: Query<&TileStorage>,
+    npc_q: Query<(
+        Entity,
+        &MapNpc,
+        &TiledObject,
+        &Transform,
+        Option<&Direction>,
+        Option<&AnimationState>,
+        Option<&AI>,
+        Option<&Dialogue>,
+    )>,
+    mut stashed_npcs: ResMut<StashedNpcs>,
+    mut npc_pool: ResMut<NpcPool>,
+) {
+    if !settings.enabled {
+        return;
+    }
+
+    let Ok(camera_transform) = camera_q.get_single() else {
+        return;
+    };
+
+    let camera_pos = camera_transform.translation.truncate();
+    let unload_distance = settings.load_distance + settings.unload_margin;
+
+    // NPCs are measured on the chunk grid of the map they're from.
+    let mut npc_grids = HashMap::new();
+
+    for (map_entity, map_handle, map_transform, layer_storage, tile_edits, mut chunks) in map_query.iter_mut() {
+        let Some(tiled_map) = maps.get(map_handle) else {
+            continue;
+        };
+
+        let map_offset = map_transform.translation.truncate();
+        let camera_chunk = settings.chunk_at(&tiled_map.map, map_offset, camera_pos);
+        npc_grids.insert(map_entity, (&tiled_map.map, map_offset, camera_chunk));
+
+        chunks.loaded.retain(|chunk, entities| {
+            if chunk_distance(*chunk, camera_chunk) <= unload_distance {
+                return true;
+            }
+
+            log::info!("Unloading chunk {chunk}");
+            for entity in entities.iter() {
+                despawn_with_tiles(&mut commands, &tile_storage_q, *entity);
+            }
+            false
+        });
+
+        let chunk_count = settings.chunk_count(&tiled_map.map);
+        let distance = settings.load_distance as i32;
+        let min = (camera_chunk - IVec2::splat(distance)).max(IVec2::ZERO);
+        let max = (camera_chunk + IVec2::splat(distance)).min(chunk_count - IVec2::ONE);
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                let chunk = IVec2::new(x, y);
+                if chunks.loaded.contains_key(&chunk) {
+                    continue;
+                }
+
+                log::info!("Loading chunk {chunk}");
+                let entities = spawn_region(
+                    &mut commands,
+                    &asset_server,
+                    tiled_map,
+                    map_entity,
+                    map_offset,
+                    layer_storage,
+                    tile_edits,
+                    &class_registry,
+                    TileRegion::chunk(&tiled_map.map, chunk, settings.chunk_size),
+                );
+                chunks.loaded.insert(chunk, entities);
+            }
+        }
+    }
+
+    // Distance in chunks between the camera and `pos`, on the grid of `map`.
+    let camera_distance = |map: Entity, pos: Vec2| {
+        let (map, map_offset, camera_chunk) = npc_grids.get(&map)?;
+        Some(chunk_distance(settings.chunk_at(map, *map_offset, pos), *camera_chunk))
+    };
+
+    // NPCs leaving the loaded area are stashed with their state...
+    for (entity, map_npc, tiled_object, transform, direction, animation_state, ai, dialogue) in npc_q.iter() {
+        match camera_distance(tiled_object.map, transform.translation.truncate()) {
+            Some(distance) if distance > unload_distance => {}
+            _ => continue,
+        }
+
+        stashed_npcs.npcs.push(NpcData {
+            name: map_npc.name.clone(),
+            class: map_npc.class.clone(),
+            pos: transform.translation,
+            properties: map_npc.properties.clone(),
+            state: NpcState {
+                direction: direction.copied(),
+                animation_state: animation_state.cloned(),
+                ai: ai.cloned(),
+                dialogue: dialogue.cloned(),
+            },
+            object: *tiled_object,
+        });
+        commands.entity(entity).despawn_recursive();
+    }
+
+    // ...and respawned once they're close again.
+    let (near, far): (Vec<_>, Vec<_>) = std::mem::take(&mut stashed_npcs.npcs)
+        .into_iter()
+        .partition(|npc| {
+            camera_distance(npc.object.map, npc.pos.truncate())
+                .map_or(false, |distance| distance <= settings.load_distance)
+        });
+
+    stashed_npcs.npcs = far;
+    if !near.is_empty() {
+        npc_pool.npcs.extend(near);
+    }
+}
@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+
+use bevy::{
+    ecs::{reflect::ReflectComponent, system::Command},
+    log,
+    prelude::{App, AppTypeRegistry, Color, Component, Entity, ReflectDefault, Resource, World},
+    reflect::{
+        DynamicEnum, DynamicStruct, DynamicVariant, GetTypeRegistration, Reflect, StructInfo,
+        TypeInfo, TypeRegistryInternal as TypeRegistry,
+    },
+};
+use tiled::{Properties, PropertyValue};
+
+/// How objects of a class registered with `register_pooled` are spawned. They're
+/// collected by `process_loaded_maps` instead of being spawned with their layer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PooledObject {
+    /// Spawned from the prototype named by the `id` property, see `spawn_npcs`.
+    Npc,
+    /// Shown when the player walks up to it, see `add_sign_sensors`.
+    Sign,
+}
+
+/// Maps Tiled class names (the `class` attribute of objects, layers and tiles)
+/// to the type name of a reflected component.
+#[derive(Resource, Default)]
+pub struct TiledClassRegistry {
+    classes: HashMap<String, &'static str>,
+    pooled: HashMap<String, PooledObject>,
+}
+
+impl TiledClassRegistry {
+    pub fn register<T: Reflect>(&mut self, class: &str) {
+        self.classes
+            .insert(class.to_string(), std::any::type_name::<T>());
+    }
+
+    pub fn get(&self, class: &str) -> Option<&'static str> {
+        self.classes.get(class).copied()
+    }
+
+    pub fn contains(&self, class: &str) -> bool {
+        self.classes.contains_key(class)
+    }
+
+    pub fn register_pooled(&mut self, class: &str, kind: PooledObject) {
+        self.pooled.insert(class.to_string(), kind);
+    }
+
+    pub fn pooled(&self, class: &str) -> Option<PooledObject> {
+        self.pooled.get(class).copied()
+    }
+
+    // The class objects of `kind` are written with. The first by name if there are several.
+    pub fn pooled_class(&self, kind: PooledObject) -> Option<&str> {
+        self.pooled
+            .iter()
+            .filter(|(_, pooled)| **pooled == kind)
+            .map(|(class, _)| class.as_str())
+            .min()
+    }
+}
+
+pub trait RegisterTiledClass {
+    /// Registers `T` as the component spawned for Tiled entities of class `class`.
+    /// `T` must reflect `Component` and `Default`.
+    fn register_tiled_class<T>(&mut self, class: &str) -> &mut Self
+    where
+        T: Component + Reflect + GetTypeRegistration + Default;
+
+    /// Registers `class` as a class of objects spawned as `kind`. They still get
+    /// the component registered for `class`, if any.
+    fn register_pooled_tiled_class(&mut self, class: &str, kind: PooledObject) -> &mut Self;
+}
+
+impl RegisterTiledClass for App {
+    fn register_tiled_class<T>(&mut self, class: &str) -> &mut Self
+    where
+        T: Component + Reflect + GetTypeRegistration + Default,
+    {
+        self.register_type::<T>();
+        self.world
+            .get_resource_or_insert_with(TiledClassRegistry::default)
+            .register::<T>(class);
+        self
+    }
+
+    fn register_pooled_tiled_class(&mut self, class: &str, kind: PooledObject) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(TiledClassRegistry::default)
+            .register_pooled(class, kind);
+        self
+    }
+}
+
+/// Deserializes `properties` into the component registered for `class`
+/// and inserts it on `entity`. Unregistered classes are ignored.
+pub struct InsertTiledClass {
+    pub entity: Entity,
+    pub class: String,
+    pub properties: Properties,
+}
+
+impl Command for InsertTiledClass {
+    fn write(self, world: &mut World) {
+        let Some(type_name) = world
+            .get_resource::<TiledClassRegistry>()
+            .and_then(|registry| registry.get(&self.class)) else {
+                return;
+            };
+
+        if world.get_entity(self.entity).is_none() {
+            return;
+        }
+
+        let type_registry = world.resource::<AppTypeRegistry>().clone();
+        let type_registry = type_registry.read();
+
+        let Some(registration) = type_registry.get_with_name(type_name) else {
+            log::warn!("Tiled class {} maps to unregistered type {type_name}", self.class);
+            return;
+        };
+
+        let (Some(reflect_default), Some(reflect_component)) = (
+            registration.data::<ReflectDefault>(),
+            registration.data::<ReflectComponent>(),
+        ) else {
+            log::warn!("Type {type_name} must reflect Component and Default to be used as a Tiled class");
+            return;
+        };
+
+        let mut component = reflect_default.default();
+        if let TypeInfo::Struct(info) = registration.type_info() {
+            let fields: Vec<(&str, &PropertyValue)> = self
+                .properties
+                .iter()
+                .map(|(name, value)| (name.as_str(), value))
+                .collect();
+            component.apply(&properties_to_struct(&type_registry, info, &fields));
+        }
+
+        reflect_component.insert(world, self.entity, component.as_ref());
+    }
+}
+
+// Builds a patch for a struct of type `info`. Properties of nested members
+// are written as `member.field`, which is how Tiled flattens class members.
+fn properties_to_struct(
+    type_registry: &TypeRegistry,
+    info: &StructInfo,
+    properties: &[(&str, &PropertyValue)],
+) -> DynamicStruct {
+    let mut patch = DynamicStruct::default();
+    patch.set_name(info.type_name().to_string());
+
+    let mut nested: HashMap<&str, Vec<(&str, &PropertyValue)>> = HashMap::new();
+    for (name, value) in properties.iter() {
+        if let Some((member, rest)) = name.split_once('.') {
+            nested.entry(member).or_default().push((rest, value));
+            continue;
+        }
+
+        // Objects also carry properties read elsewhere, such as the `id`, `z` and `dialogue`
+        // of pooled objects and the overrides of NPCs, so these aren't worth a warning.
+        let Some(field) = info.field(name) else {
+            log::debug!("{} has no field named {name}", info.type_name());
+            continue;
+        };
+
+        match property_to_reflect(type_registry, value, field.type_name()) {
+            Some(value) => patch.insert_boxed(name, value),
+            None => log::warn!(
+                "Property {name} can't be converted to {}",
+                field.type_name()
+            ),
+        }
+    }
+
+    for (member, properties) in nested {
+        let Some(field) = info.field(member) else {
+            log::debug!("{} has no field named {member}", info.type_name());
+            continue;
+        };
+
+        let member_info = type_registry
+            .get_with_name(field.type_name())
+            .map(|registration| registration.type_info());
+        if let Some(TypeInfo::Struct(member_info)) = member_info {
+            let value = properties_to_struct(type_registry, member_info, &properties);
+            patch.insert(member, value);
+        }
+    }
+
+    patch
+}
+
+fn property_to_reflect(
+    type_registry: &TypeRegistry,
+    value: &PropertyValue,
+    type_name: &str,
+) -> Option<Box<dyn Reflect>> {
+    match value {
+        PropertyValue::BoolValue(b) => (type_name == "bool").then(|| Box::new(*b) as Box<dyn Reflect>),
+        PropertyValue::IntValue(i) => int_to_reflect(type_registry, *i as i64, type_name),
+        PropertyValue::ObjectValue(id) => int_to_reflect(type_registry, *id as i64, type_name),
+        PropertyValue::FloatValue(f) => match type_name {
+            "f32" => Some(Box::new(*f)),
+            "f64" => Some(Box::new(*f as f64)),
+            _ => None,
+        },
+        PropertyValue::StringValue(s) | PropertyValue::FileValue(s) => {
+            if type_name == std::any::type_name::<String>() {
+                return Some(Box::new(s.clone()));
+            }
+
+            // Tiled stores enum properties by their variant name.
+            let Some(TypeInfo::Enum(info)) = type_registry
+                .get_with_name(type_name)
+                .map(|registration| registration.type_info()) else {
+                    return None;
+                };
+
+            info.variant(s)?;
+            Some(Box::new(DynamicEnum::new(type_name, s.as_str(), DynamicVariant::Unit)))
+        }
+        PropertyValue::ColorValue(c) => {
+            (type_name == std::any::type_name::<Color>()).then(|| {
+                Box::new(Color::rgba_u8(c.red, c.green, c.blue, c.alpha)) as Box<dyn Reflect>
+            })
+        }
+    }
+}
+
+fn int_to_reflect(
+    type_registry: &TypeRegistry,
+    i: i64,
+    type_name: &str,
+) -> Option<Box<dyn Reflect>> {
+    let value: Box<dyn Reflect> = match type_name {
+        "i8" => Box::new(i8::try_from(i).ok()?),
+        "i16" => Box::new(i16::try_from(i).ok()?),
+        "i32" => Box::new(i32::try_from(i).ok()?),
+        "i64" => Box::new(i),
+        "isize" => Box::new(isize::try_from(i).ok()?),
+        "u8" => Box::new(u8::try_from(i).ok()?),
+        "u16" => Box::new(u16::try_from(i).ok()?),
+        "u32" => Box::new(u32::try_from(i).ok()?),
+        "u64" => Box::new(u64::try_from(i).ok()?),
+        "usize" => Box::new(usize::try_from(i).ok()?),
+        "f32" => Box::new(i as f32),
+        "f64" => Box::new(i as f64),
+        _ => {
+            // Enums with the "save as number" option are stored by variant index.
+            let Some(TypeInfo::Enum(info)) = type_registry
+                .get_with_name(type_name)
+                .map(|registration| registration.type_info()) else {
+                    return None;
+                };
+
+            let variant = info.variant_at(usize::try_from(i).ok()?)?;
+            Box::new(DynamicEnum::new(type_name, variant.name(), DynamicVariant::Unit))
+        }
+    };
+
+    Some(value)
+}
@@ -0,0 +1,256 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use tiled::ObjectShape;
+
+// A tile's orientation as encoded by Tiled's flip flags. The diagonal flip is applied
+// first, then the horizontal and the vertical ones - the same as when rendering the tile.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TileOrientation {
+    pub flip_h: bool,
+    pub flip_v: bool,
+    pub flip_d: bool,
+}
+
+impl TileOrientation {
+    // Transforms an offset from the center of the tile, with y pointing up.
+    pub fn apply(&self, offset: Vec2) -> Vec2 {
+        let mut offset = offset;
+
+        // The diagonal flip mirrors over the top-left to bottom-right diagonal.
+        if self.flip_d {
+            offset = Vec2::new(-offset.y, -offset.x);
+        }
+
+        if self.flip_h {
+            offset.x = -offset.x;
+        }
+
+        if self.flip_v {
+            offset.y = -offset.y;
+        }
+
+        offset
+    }
+
+    pub fn apply_half_extents(&self, half_extents: Vec2) -> Vec2 {
+        if self.flip_d {
+            Vec2::new(half_extents.y, half_extents.x)
+        } else {
+            half_extents
+        }
+    }
+
+    // Sprites can only be flipped along their axes, so the diagonal flip
+    // is done by mirroring along x and rotating by 90 degrees.
+    // Returns (flip_x, flip_y, rotation).
+    pub fn sprite_flips(&self) -> (bool, bool, Quat) {
+        if self.flip_d {
+            (!self.flip_v, self.flip_h, Quat::from_rotation_z(std::f32::consts::FRAC_PI_2))
+        } else {
+            (self.flip_h, self.flip_v, Quat::IDENTITY)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum TileCollisionShape {
+    Rect { half_extents: Vec2 },
+    Circle { radius: f32 },
+}
+
+impl TileCollisionShape {
+    // Whether a point relative to the center of the shape is inside it.
+    pub fn contains(&self, point: Vec2) -> bool {
+        match self {
+            TileCollisionShape::Rect { half_extents } => {
+                point.x.abs() <= half_extents.x && point.y.abs() <= half_extents.y
+            }
+            TileCollisionShape::Circle { radius } => point.length() <= *radius,
+        }
+    }
+
+    pub fn half_extents(&self) -> Vec2 {
+        match self {
+            TileCollisionShape::Rect { half_extents } => *half_extents,
+            TileCollisionShape::Circle { radius } => Vec2::splat(*radius),
+        }
+    }
+}
+
+// The collision shapes of a tile along with their offsets from the center of
+// the tile image of size `tile_size`, flipped and rotated along with the image.
+pub(crate) fn tile_collision_shapes(
+    tile: &tiled::Tile,
+    tile_size: Vec2,
+    orientation: TileOrientation,
+) -> Vec<(Vec2, TileCollisionShape)> {
+    let Some(collisions) = &tile.collision else {
+        return Vec::new();
+    };
+
+    let mut shapes = Vec::new();
+    for c in collisions.object_data() {
+        let half_extents = match &c.shape {
+            ObjectShape::Rect { width, height } => Vec2::new(width / 2.0, height / 2.0),
+            ObjectShape::Ellipse { width, height: _ } => Vec2::splat(width / 2.0),
+            _ => continue,
+        };
+
+        // Offset since rapier places colliders such that
+        // their center of mass lies on the given position
+        // Tiled on the other hand uses offset from the upper-left
+        // corner of the tile.
+        let offset = Vec2::new(
+            c.x + (half_extents.x - tile_size.x / 2.0),
+            tile_size.y / 2.0 - half_extents.y - c.y,
+        );
+        let offset = orientation.apply(offset);
+        let half_extents = orientation.apply_half_extents(half_extents);
+
+        let shape = match &c.shape {
+            ObjectShape::Ellipse { .. } => TileCollisionShape::Circle {
+                radius: half_extents.x,
+            },
+            _ => TileCollisionShape::Rect { half_extents },
+        };
+        shapes.push((offset, shape));
+    }
+
+    shapes
+}
+
+// Spawns the collision shapes of a tile as children of `tile_entity`, which is expected
+// to be placed at the center of the tile image of size `tile_size`. The shapes are
+// flipped and rotated along with the tile image.
+// Returns whether any collider was spawned.
+pub(crate) fn spawn_tile_colliders(
+    commands: &mut Commands,
+    tile_entity: Entity,
+    tile: &tiled::Tile,
+    tile_size: Vec2,
+    orientation: TileOrientation,
+) -> bool {
+    let shapes = tile_collision_shapes(tile, tile_size, orientation);
+    for (offset, shape) in shapes.iter() {
+        let collider = match shape {
+            TileCollisionShape::Circle { radius } => Collider::ball(*radius),
+            TileCollisionShape::Rect { half_extents } => {
+                Collider::cuboid(half_extents.x, half_extents.y)
+            }
+        };
+
+        let collider_entt = commands
+            .spawn(collider)
+            .insert(TransformBundle::from(Transform::from_translation(offset.extend(0.0))))
+            .id();
+
+        commands.entity(tile_entity).add_child(collider_entt);
+    }
+
+    !shapes.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn orientation(flip_h: bool, flip_v: bool, flip_d: bool) -> TileOrientation {
+        TileOrientation {
+            flip_h,
+            flip_v,
+            flip_d,
+        }
+    }
+
+    // Every combination of the three flips.
+    fn all_orientations() -> impl Iterator<Item = TileOrientation> {
+        (0..8).map(|bits| orientation(bits & 1 != 0, bits & 2 != 0, bits & 4 != 0))
+    }
+
+    // Where Tiled draws the point at `offset` of a tile image of `size`. Tiled flips
+    // the image in pixel coordinates, with y pointing down, diagonally first.
+    fn tiled_reference(orientation: TileOrientation, offset: Vec2, size: Vec2) -> Vec2 {
+        let mut size = size;
+        let (mut u, mut v) = (offset.x + size.x / 2.0, size.y / 2.0 - offset.y);
+        if orientation.flip_d {
+            (u, v) = (v, u);
+            size = Vec2::new(size.y, size.x);
+        }
+        if orientation.flip_h {
+            u = size.x - u;
+        }
+        if orientation.flip_v {
+            v = size.y - v;
+        }
+
+        Vec2::new(u - size.x / 2.0, size.y / 2.0 - v)
+    }
+
+    #[test]
+    fn apply_flips_offsets() {
+        let offset = Vec2::new(3.0, 1.0);
+        let cases = [
+            (orientation(false, false, false), Vec2::new(3.0, 1.0)),
+            (orientation(true, false, false), Vec2::new(-3.0, 1.0)),
+            (orientation(false, true, false), Vec2::new(3.0, -1.0)),
+            (orientation(false, false, true), Vec2::new(-1.0, -3.0)),
+            (orientation(true, true, false), Vec2::new(-3.0, -1.0)),
+            (orientation(true, false, true), Vec2::new(1.0, -3.0)),
+            (orientation(false, true, true), Vec2::new(-1.0, 3.0)),
+            (orientation(true, true, true), Vec2::new(1.0, 3.0)),
+        ];
+
+        for (orientation, expected) in cases {
+            assert_eq!(orientation.apply(offset), expected, "{orientation:?}");
+        }
+    }
+
+    #[test]
+    fn apply_matches_tiled() {
+        let size = Vec2::splat(32.0);
+        for orientation in all_orientations() {
+            for offset in [Vec2::new(3.0, 1.0), Vec2::new(-10.0, 4.0), Vec2::new(16.0, -16.0)] {
+                assert_eq!(
+                    orientation.apply(offset),
+                    tiled_reference(orientation, offset, size),
+                    "{orientation:?} at {offset}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn apply_half_extents_swaps_on_diagonal_flips() {
+        let half_extents = Vec2::new(8.0, 2.0);
+        for orientation in all_orientations() {
+            let expected = if orientation.flip_d {
+                Vec2::new(2.0, 8.0)
+            } else {
+                half_extents
+            };
+            assert_eq!(orientation.apply_half_extents(half_extents), expected, "{orientation:?}");
+            // The extents are those of the flipped corner.
+            assert_eq!(orientation.apply(half_extents).abs(), expected, "{orientation:?}");
+        }
+    }
+
+    #[test]
+    fn sprite_flips_match_apply() {
+        for orientation in all_orientations() {
+            let (flip_x, flip_y, rotation) = orientation.sprite_flips();
+            for offset in [Vec2::new(3.0, 1.0), Vec2::new(-10.0, 4.0)] {
+                // Sprites are flipped in their own space, then rotated by their transform.
+                let flipped = Vec2::new(
+                    if flip_x { -offset.x } else { offset.x },
+                    if flip_y { -offset.y } else { offset.y },
+                );
+                let drawn = (rotation * flipped.extend(0.0)).truncate();
+                assert!(
+                    drawn.abs_diff_eq(orientation.apply(offset), 1e-5),
+                    "{orientation:?}: {drawn} != {}",
+                    orientation.apply(offset)
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,387 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    chunks::{ChunkStreaming, MapChunks, TileRegion},
+    collision::TileOrientation,
+    flattened_layers, tile_layer, FlattenedLayer, TiledLayer, TiledLayersStorage, TiledMap,
+    TiledSpriteTile, RebuildNavGrid,
+};
+
+/// A tile of one of the map's tilesets, as placed on a tile layer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MapTile {
+    pub tileset_index: usize,
+    pub tile_id: u32,
+    #[serde(default)]
+    pub flip_h: bool,
+    #[serde(default)]
+    pub flip_v: bool,
+    #[serde(default)]
+    pub flip_d: bool,
+}
+
+impl MapTile {
+    pub fn new(tileset_index: usize, tile_id: u32) -> Self {
+        Self {
+            tileset_index,
+            tile_id,
+            flip_h: false,
+            flip_v: false,
+            flip_d: false,
+        }
+    }
+
+    pub fn orientation(&self) -> TileOrientation {
+        TileOrientation {
+            flip_h: self.flip_h,
+            flip_v: self.flip_v,
+            flip_d: self.flip_d,
+        }
+    }
+
+    pub fn tile<'map>(&self, map: &'map tiled::Map) -> Option<tiled::Tile<'map>> {
+        map.tilesets().get(self.tileset_index)?.get_tile(self.tile_id)
+    }
+}
+
+impl From<&tiled::LayerTileData> for MapTile {
+    fn from(data: &tiled::LayerTileData) -> Self {
+        Self {
+            tileset_index: data.tileset_index(),
+            tile_id: data.id(),
+            flip_h: data.flip_h,
+            flip_v: data.flip_v,
+            flip_d: data.flip_d,
+        }
+    }
+}
+
+// The edited tiles of a layer. None if the tile was removed.
+pub(crate) type LayerEdits = HashMap<UVec2, Option<MapTile>>;
+
+/// The tiles set or removed at runtime, per tile layer name.
+///
+/// Stored on the map entity and applied whenever the map's tiles are spawned,
+/// so the edits survive chunk streaming and reloads of the map. To restore saved
+/// edits, insert them along with the `TiledMapBundle`.
+#[derive(Component, Default, Clone, Debug, Serialize, Deserialize)]
+#[serde(from = "Vec<TileEdit>", into = "Vec<TileEdit>")]
+pub struct TileEdits {
+    layers: HashMap<String, LayerEdits>,
+}
+
+/// A single recorded edit, see `TileEdits`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TileEdit {
+    pub layer: String,
+    pub pos: UVec2,
+    pub tile: Option<MapTile>,
+}
+
+impl TileEdits {
+    /// The tile set at `pos`, Some(None) if it was removed and None if it wasn't edited.
+    pub fn get(&self, layer: &str, pos: TilePos) -> Option<Option<MapTile>> {
+        self.layers.get(layer)?.get(&UVec2::new(pos.x, pos.y)).copied()
+    }
+
+    pub fn set(&mut self, layer: &str, pos: TilePos, tile: Option<MapTile>) {
+        self.layers
+            .entry(layer.to_string())
+            .or_default()
+            .insert(UVec2::new(pos.x, pos.y), tile);
+    }
+
+    pub fn clear(&mut self) {
+        self.layers.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.values().all(|edits| edits.is_empty())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = TileEdit> + '_ {
+        self.layers.iter().flat_map(|(layer, edits)| {
+            edits.iter().map(|(pos, tile)| TileEdit {
+                layer: layer.clone(),
+                pos: *pos,
+                tile: *tile,
+            })
+        })
+    }
+
+    pub(crate) fn layer(&self, layer: &str) -> Option<&LayerEdits> {
+        self.layers.get(layer)
+    }
+}
+
+impl From<Vec<TileEdit>> for TileEdits {
+    fn from(edits: Vec<TileEdit>) -> Self {
+        let mut tile_edits = TileEdits::default();
+        for edit in edits {
+            tile_edits.set(&edit.layer, TilePos { x: edit.pos.x, y: edit.pos.y }, edit.tile);
+        }
+        tile_edits
+    }
+}
+
+impl From<TileEdits> for Vec<TileEdit> {
+    fn from(edits: TileEdits) -> Self {
+        edits.iter().collect()
+    }
+}
+
+/// Sets the tile at `pos` on the tile layer named `layer` of `map`, or removes it if
+/// `tile` is None. Positions are the same as in `TileQuery`.
+///
+/// The tile's entity is respawned along with its colliders and class components,
+/// and the `NavGrid` is rebuilt. The edit is recorded in the map's `TileEdits`.
+pub struct EditTile {
+    pub map: Entity,
+    pub layer: String,
+    pub pos: TilePos,
+    pub tile: Option<MapTile>,
+}
+
+impl EditTile {
+    pub fn set(map: Entity, layer: impl Into<String>, pos: TilePos, tile: MapTile) -> Self {
+        Self {
+            map,
+            layer: layer.into(),
+            pos,
+            tile: Some(tile),
+        }
+    }
+
+    pub fn remove(map: Entity, layer: impl Into<String>, pos: TilePos) -> Self {
+        Self {
+            map,
+            layer: layer.into(),
+            pos,
+            tile: None,
+        }
+    }
+}
+
+// The tile at `pos` of a tile layer, with the edits applied.
+pub(crate) fn layer_tile(
+    map: &tiled::Map,
+    layer_data: &tiled::FiniteTileLayer,
+    layer_edits: Option<&LayerEdits>,
+    pos: UVec2,
+) -> Option<MapTile> {
+    if let Some(edit) = layer_edits.and_then(|edits| edits.get(&pos)) {
+        return *edit;
+    }
+
+    let mut mapped_y = pos.y;
+    if map.orientation == tiled::Orientation::Orthogonal {
+        mapped_y = (map.height - 1) - pos.y;
+    }
+
+    layer_data
+        .get_tile_data(pos.x as i32, mapped_y as i32)
+        .map(MapTile::from)
+}
+
+fn check_edit(map: &tiled::Map, edit: &EditTile) -> Result<(), String> {
+    if edit.pos.x >= map.width || edit.pos.y >= map.height {
+        return Err(format!("{:?} is outside of the map", edit.pos));
+    }
+
+    if find_tile_layer(&flattened_layers(map, Vec2::ZERO), &edit.layer).is_none() {
+        return Err(format!("There's no tile layer {}", edit.layer));
+    }
+
+    if let Some(tile) = edit.tile {
+        if tile.tile(map).is_none() {
+            return Err(format!("Tileset {} has no tile {}", tile.tileset_index, tile.tile_id));
+        }
+    }
+
+    Ok(())
+}
+
+fn find_tile_layer<'a, 'map>(
+    layers: &'a [FlattenedLayer<'map>],
+    name: &str,
+) -> Option<(usize, &'a FlattenedLayer<'map>, tiled::FiniteTileLayer<'map>)> {
+    layers.iter().enumerate().find_map(|(layer_index, flattened_layer)| {
+        if flattened_layer.layer.name != name {
+            return None;
+        }
+
+        match flattened_layer.layer.layer_type() {
+            tiled::LayerType::TileLayer(tiled::TileLayer::Finite(layer_data)) => {
+                Some((layer_index, flattened_layer, layer_data))
+            }
+            _ => None,
+        }
+    })
+}
+
+// The position of `pos` within a tilemap, if the tilemap covers it.
+fn local_tile_pos(tiled_layer: &TiledLayer, storage: &TileStorage, pos: TilePos) -> Option<TilePos> {
+    let local_pos = TilePos {
+        x: pos.x.checked_sub(tiled_layer.origin.x)?,
+        y: pos.y.checked_sub(tiled_layer.origin.y)?,
+    };
+    local_pos.within_map_bounds(&storage.size).then_some(local_pos)
+}
+
+pub fn apply_tile_edits(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut events: EventReader<EditTile>,
+    maps: Res<Assets<TiledMap>>,
+    chunk_streaming: Res<ChunkStreaming>,
+    mut map_query: Query<(
+        &Handle<TiledMap>,
+        &Transform,
+        &TiledLayersStorage,
+        &mut MapChunks,
+        &mut TileEdits,
+    )>,
+    children_q: Query<&Children>,
+    mut tilemap_q: Query<(&TiledLayer, &mut TileStorage)>,
+    sprite_tile_q: Query<&TiledSpriteTile>,
+    mut rebuild_nav_grid: EventWriter<RebuildNavGrid>,
+) {
+    // Tiles edited several times within a frame are only respawned once.
+    let mut changed_tiles: Vec<(Entity, String, TilePos)> = Vec::new();
+    for edit in events.iter() {
+        let Ok((map_handle, .., mut edits)) = map_query.get_mut(edit.map) else {
+            log::warn!("Can't edit a tile of {:?}, it isn't a map", edit.map);
+            continue;
+        };
+
+        // Edits of maps that aren't loaded yet are checked once they're spawned.
+        if let Some(tiled_map) = maps.get(map_handle) {
+            if let Err(e) = check_edit(&tiled_map.map, edit) {
+                log::warn!("Skipped tile edit: {e}");
+                continue;
+            }
+        }
+
+        edits.set(&edit.layer, edit.pos, edit.tile);
+
+        let changed_tile = (edit.map, edit.layer.clone(), edit.pos);
+        if !changed_tiles.contains(&changed_tile) {
+            changed_tiles.push(changed_tile);
+        }
+    }
+
+    let mut changed_maps = Vec::new();
+    for (map_entity, layer_name, pos) in changed_tiles {
+        let Ok((map_handle, map_transform, layer_storage, mut chunks, edits)) = map_query.get_mut(map_entity) else {
+            continue;
+        };
+        let Some(tiled_map) = maps.get(map_handle) else {
+            continue;
+        };
+
+        if !changed_maps.contains(&map_entity) {
+            changed_maps.push(map_entity);
+        }
+
+        let layers = flattened_layers(&tiled_map.map, map_transform.translation.truncate());
+        let Some((layer_index, flattened_layer, layer_data)) = find_tile_layer(&layers, &layer_name) else {
+            continue;
+        };
+        let Some(&layer_entity) = layer_storage.storage.get(&(layer_index as u32)) else {
+            continue;
+        };
+
+        // Chunks that aren't loaded get the edit once they are.
+        let chunk = (UVec2::new(pos.x, pos.y) / chunk_streaming.chunk_size).as_ivec2();
+        if chunk_streaming.enabled && !chunks.loaded.contains_key(&chunk) {
+            continue;
+        }
+
+        let children: Vec<Entity> = children_q
+            .get(layer_entity)
+            .map(|children| children.to_vec())
+            .unwrap_or_default();
+
+        // Colliders and class components go along with the tile entity.
+        for child in children.iter() {
+            if let Ok((tiled_layer, mut storage)) = tilemap_q.get_mut(*child) {
+                let Some(local_pos) = local_tile_pos(tiled_layer, &storage, pos) else {
+                    continue;
+                };
+
+                if let Some(tile_entity) = storage.get(&local_pos) {
+                    commands.entity(tile_entity).despawn_recursive();
+                    storage.remove(&local_pos);
+                }
+            } else if sprite_tile_q.get(*child).map_or(false, |sprite_tile| sprite_tile.0 == pos) {
+                commands.entity(*child).despawn_recursive();
+                for entities in chunks.loaded.values_mut() {
+                    entities.retain(|entity| entity != child);
+                }
+            }
+        }
+
+        let layer_edits = edits.layer(&layer_name);
+        let Some(map_tile) = layer_tile(&tiled_map.map, &layer_data, layer_edits, UVec2::new(pos.x, pos.y)) else {
+            continue;
+        };
+
+        // The tile goes into a tilemap of its tileset covering it. Tilemaps of y-sorted
+        // layers are split by row, the tile goes into the one of its row.
+        let row = flattened_layer.y_sort_offset().map(|_| pos.y);
+        let tilemap = children.iter().find_map(|child| {
+            let (tiled_layer, storage) = tilemap_q.get(*child).ok()?;
+            if tiled_layer.tileset_index != map_tile.tileset_index || tiled_layer.row != row {
+                return None;
+            }
+
+            local_tile_pos(tiled_layer, storage, pos).map(|local_pos| (*child, local_pos))
+        });
+
+        if let Some((tilemap_entity, local_pos)) = tilemap {
+            if let Some(tile_entity) = tile_layer::spawn_tile(
+                &mut commands,
+                tiled_map,
+                flattened_layer,
+                map_tile,
+                tilemap_entity,
+                pos,
+                local_pos,
+            ) {
+                if let Ok((_, mut storage)) = tilemap_q.get_mut(tilemap_entity) {
+                    storage.set(&local_pos, tile_entity);
+                }
+            }
+            continue;
+        }
+
+        // Otherwise no tilemap of the tileset covers the tile, or its row, yet.
+        // A tilemap, or a sprite, is spawned for the single tile.
+        let entities = tile_layer::spawn_tile_layer(
+            &mut commands,
+            &asset_server,
+            tiled_map,
+            map_entity,
+            flattened_layer,
+            &layer_data,
+            layer_edits,
+            map_tile.tileset_index,
+            TileRegion {
+                min: UVec2::new(pos.x, pos.y),
+                size: UVec2::ONE,
+            },
+        );
+        commands.entity(layer_entity).push_children(&entities);
+        if let Some(chunk_entities) = chunks.loaded.get_mut(&chunk) {
+            chunk_entities.extend(entities);
+        }
+    }
+
+    for map in changed_maps {
+        rebuild_nav_grid.send(RebuildNavGrid { map });
+    }
+}
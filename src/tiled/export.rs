@@ -0,0 +1,1057 @@
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use prototyp::tmx::escape;
+use relative_path::RelativePath;
+use tiled::{Properties, PropertyValue};
+
+use crate::{
+    components::{Controllable, MapNpc},
+    prototypes::PrototypeName,
+    resources::{NpcPool, SignsPool, StashedNpcs},
+};
+
+use super::{
+    edit::{self, TileEdits},
+    flattened_layers, map_top_left, GeneratedMap, MapSources, PooledObject, TemplateInstance,
+    TiledClassRegistry, TiledMap, TiledObject,
+};
+
+// Flags Tiled stores in the upper bits of a global tile id.
+const FLIPPED_HORIZONTALLY: u32 = 0x80000000;
+const FLIPPED_VERTICALLY: u32 = 0x40000000;
+const FLIPPED_DIAGONALLY: u32 = 0x20000000;
+
+/// Writes the map entity `map` to a TMX file at `path`, which Tiled can open and
+/// `TiledLoader` can load again.
+///
+/// Tiles are written as edited at runtime and objects at their current position.
+/// NPCs keep the properties they were spawned with. Prototypes spawned and signs added
+/// at runtime become new objects of the first object layer. External tilesets and
+/// templates are referred to like in the loaded file.
+pub struct ExportMap {
+    pub map: Entity,
+    pub path: PathBuf,
+}
+
+// How far an object moved since it was spawned and, for NPCs, its current properties.
+#[derive(Default)]
+pub(crate) struct ObjectOverride {
+    offset: Vec2,
+    properties: Option<Properties>,
+}
+
+// An object which isn't part of the loaded map, e.g. added at runtime.
+pub(crate) struct NewObject {
+    pub class: String,
+    pub template: Option<PathBuf>,
+    // Position in Tiled's pixel coordinates, i.e y points down.
+    pub pos: Vec2,
+    pub size: Vec2,
+    pub properties: Properties,
+}
+
+// Writes a TMX file.
+pub(crate) struct TmxWriter<'a> {
+    tmx: String,
+    // The directory the file is written to, image paths are relative to it.
+    dir: &'a Path,
+    first_gids: Vec<u32>,
+    sources: &'a MapSources,
+    tile_edits: &'a TileEdits,
+    objects: &'a HashMap<u32, ObjectOverride>,
+    // Written into the first object layer, with ids from `next_object_id` on.
+    new_objects: &'a [NewObject],
+    next_object_id: u32,
+    new_objects_written: bool,
+}
+
+fn color_to_string(color: &tiled::Color) -> String {
+    format!(
+        "#{:02x}{:02x}{:02x}{:02x}",
+        color.alpha, color.red, color.green, color.blue
+    )
+}
+
+// Tile ids of a tileset take up the global ids from its first gid on.
+// Image collections may have gaps between their tile ids.
+fn gid_count(tileset: &tiled::Tileset) -> u32 {
+    let max_id = tileset.tiles().map(|(id, _)| id + 1).max().unwrap_or(0);
+    tileset.tilecount.max(max_id)
+}
+
+fn max_ids<'map>(
+    layers: impl Iterator<Item = tiled::Layer<'map>>,
+    max_layer_id: &mut u32,
+    max_object_id: &mut u32,
+) {
+    for layer in layers {
+        *max_layer_id = (*max_layer_id).max(layer.id());
+        match layer.layer_type() {
+            tiled::LayerType::ObjectLayer(obj_layer) => {
+                for object in obj_layer.objects() {
+                    *max_object_id = (*max_object_id).max(object.id());
+                }
+            }
+            tiled::LayerType::GroupLayer(group) => {
+                max_ids(group.layers(), max_layer_id, max_object_id)
+            }
+            _ => {}
+        }
+    }
+}
+
+impl TmxWriter<'_> {
+    fn line(&mut self, indent: usize, line: &str) {
+        let _ = writeln!(self.tmx, "{}{}", " ".repeat(indent), line);
+    }
+
+    // Paths resolved by the tiled crate are relative to the working directory.
+    fn relative_path(&self, path: &Path) -> String {
+        let dir = self.dir.to_string_lossy().replace('\\', "/");
+        let path = path.to_string_lossy().replace('\\', "/");
+        RelativePath::new(&dir)
+            .relative(RelativePath::new(&path))
+            .to_string()
+    }
+
+    fn gid(
+        &self,
+        tileset_index: usize,
+        tile_id: u32,
+        flip_h: bool,
+        flip_v: bool,
+        flip_d: bool,
+    ) -> u32 {
+        let mut gid = self.first_gids[tileset_index] + tile_id;
+        if flip_h {
+            gid |= FLIPPED_HORIZONTALLY;
+        }
+        if flip_v {
+            gid |= FLIPPED_VERTICALLY;
+        }
+        if flip_d {
+            gid |= FLIPPED_DIAGONALLY;
+        }
+        gid
+    }
+
+    fn properties(&mut self, indent: usize, properties: &Properties) {
+        if properties.is_empty() {
+            return;
+        }
+
+        // Sorted, so exporting the same map twice gives the same file.
+        let mut names: Vec<_> = properties.keys().collect();
+        names.sort();
+
+        self.line(indent, "<properties>");
+        for name in names {
+            let (value_type, value) = match &properties[name] {
+                PropertyValue::BoolValue(v) => ("bool", v.to_string()),
+                PropertyValue::FloatValue(v) => ("float", v.to_string()),
+                PropertyValue::IntValue(v) => ("int", v.to_string()),
+                PropertyValue::ColorValue(v) => ("color", color_to_string(v)),
+                PropertyValue::StringValue(v) => ("string", v.clone()),
+                PropertyValue::FileValue(v) => ("file", v.clone()),
+                PropertyValue::ObjectValue(v) => ("object", v.to_string()),
+            };
+            self.line(
+                indent + 1,
+                &format!(
+                    r#"<property name="{}" type="{value_type}" value="{}"/>"#,
+                    escape(name),
+                    escape(&value)
+                ),
+            );
+        }
+        self.line(indent, "</properties>");
+    }
+
+    fn image(&mut self, indent: usize, image: &tiled::Image) {
+        let mut line = format!(
+            r#"<image source="{}" width="{}" height="{}""#,
+            escape(&self.relative_path(&image.source)),
+            image.width,
+            image.height
+        );
+        if let Some(color) = &image.transparent_colour {
+            let _ = write!(
+                line,
+                r#" trans="{:02x}{:02x}{:02x}""#,
+                color.red, color.green, color.blue
+            );
+        }
+        line.push_str("/>");
+        self.line(indent, &line);
+    }
+
+    fn tileset(&mut self, indent: usize, first_gid: u32, tileset: &tiled::Tileset) {
+        self.line(
+            indent,
+            &format!(
+                r#"<tileset firstgid="{first_gid}" name="{}" tilewidth="{}" tileheight="{}" spacing="{}" margin="{}" tilecount="{}" columns="{}">"#,
+                escape(&tileset.name),
+                tileset.tile_width,
+                tileset.tile_height,
+                tileset.spacing,
+                tileset.margin,
+                tileset.tilecount,
+                tileset.columns
+            ),
+        );
+        if tileset.offset_x != 0 || tileset.offset_y != 0 {
+            self.line(
+                indent + 1,
+                &format!(
+                    r#"<tileoffset x="{}" y="{}"/>"#,
+                    tileset.offset_x, tileset.offset_y
+                ),
+            );
+        }
+        self.properties(indent + 1, &tileset.properties);
+        if let Some(image) = &tileset.image {
+            self.image(indent + 1, image);
+        }
+
+        let mut tiles: Vec<_> = tileset.tiles().collect();
+        tiles.sort_by_key(|(id, _)| *id);
+        for (id, tile) in tiles {
+            let mut line = format!(r#"<tile id="{id}""#);
+            if let Some(class) = &tile.tile_type {
+                let _ = write!(line, r#" class="{}""#, escape(class));
+            }
+            if tile.probability != 1.0 {
+                let _ = write!(line, r#" probability="{}""#, tile.probability);
+            }
+            line.push('>');
+            self.line(indent + 1, &line);
+
+            self.properties(indent + 2, &tile.properties);
+            if let Some(image) = &tile.image {
+                self.image(indent + 2, image);
+            }
+            if let Some(collision) = &tile.collision {
+                self.line(indent + 2, r#"<objectgroup draworder="index">"#);
+                for object in collision.object_data() {
+                    self.object(indent + 3, object, None, None, None);
+                }
+                self.line(indent + 2, "</objectgroup>");
+            }
+            if let Some(frames) = &tile.animation {
+                self.line(indent + 2, "<animation>");
+                for frame in frames {
+                    self.line(
+                        indent + 3,
+                        &format!(
+                            r#"<frame tileid="{}" duration="{}"/>"#,
+                            frame.tile_id, frame.duration
+                        ),
+                    );
+                }
+                self.line(indent + 2, "</animation>");
+            }
+
+            self.line(indent + 1, "</tile>");
+        }
+
+        self.line(indent, "</tileset>");
+    }
+
+    // The attributes shared by all kinds of layers.
+    fn layer_attributes(&self, layer: &tiled::Layer) -> String {
+        let mut attributes = format!(r#"id="{}" name="{}""#, layer.id(), escape(&layer.name));
+        if let Some(class) = &layer.user_type {
+            let _ = write!(attributes, r#" class="{}""#, escape(class));
+        }
+        if !layer.visible {
+            attributes.push_str(r#" visible="0""#);
+        }
+        if layer.opacity != 1.0 {
+            let _ = write!(attributes, r#" opacity="{}""#, layer.opacity);
+        }
+        if let Some(tint) = &layer.tint_color {
+            let _ = write!(attributes, r#" tintcolor="{}""#, color_to_string(tint));
+        }
+        if layer.offset_x != 0.0 || layer.offset_y != 0.0 {
+            let _ = write!(
+                attributes,
+                r#" offsetx="{}" offsety="{}""#,
+                layer.offset_x, layer.offset_y
+            );
+        }
+        if layer.parallax_x != 1.0 || layer.parallax_y != 1.0 {
+            let _ = write!(
+                attributes,
+                r#" parallaxx="{}" parallaxy="{}""#,
+                layer.parallax_x, layer.parallax_y
+            );
+        }
+        attributes
+    }
+
+    // Objects created from a template only get the attributes and properties
+    // set on them, or changed at runtime. The others come from the template.
+    fn object(
+        &mut self,
+        indent: usize,
+        object: &tiled::ObjectData,
+        tile_gid: Option<u32>,
+        object_override: Option<&ObjectOverride>,
+        template: Option<&TemplateInstance>,
+    ) {
+        // Tiled's y axis points down.
+        let offset = object_override.map_or(Vec2::ZERO, |o| o.offset);
+        let x = object.x + offset.x;
+        let y = object.y - offset.y;
+        let own = |attribute: &str| template.map_or(true, |t| t.attributes.contains(attribute));
+
+        let mut line = format!(r#"<object id="{}""#, object.id());
+        if let Some(template) = template {
+            let _ = write!(
+                line,
+                r#" template="{}""#,
+                escape(&self.relative_path(&template.template))
+            );
+        }
+        if own("name") && !object.name.is_empty() {
+            let _ = write!(line, r#" name="{}""#, escape(&object.name));
+        }
+        if own("class") && !object.user_type.is_empty() {
+            let _ = write!(line, r#" class="{}""#, escape(&object.user_type));
+        }
+        if let Some(gid) = tile_gid.filter(|_| own("gid")) {
+            let _ = write!(line, r#" gid="{gid}""#);
+        }
+        let _ = write!(line, r#" x="{x}" y="{y}""#);
+        match &object.shape {
+            tiled::ObjectShape::Rect { width, height }
+            | tiled::ObjectShape::Ellipse { width, height }
+                if own("width") || own("height") =>
+            {
+                let _ = write!(line, r#" width="{width}" height="{height}""#);
+            }
+            _ => {}
+        }
+        if own("rotation") && object.rotation != 0.0 {
+            let _ = write!(line, r#" rotation="{}""#, object.rotation);
+        }
+        if own("visible") && !object.visible {
+            line.push_str(r#" visible="0""#);
+        }
+        line.push('>');
+        self.line(indent, &line);
+
+        let mut properties = object_override
+            .and_then(|o| o.properties.clone())
+            .unwrap_or_else(|| object.properties.clone());
+        if let Some(template) = template {
+            properties.retain(|name, value| {
+                template.properties.contains(name) || object.properties.get(name) != Some(value)
+            });
+        }
+        self.properties(indent + 1, &properties);
+
+        let points_to_string = |points: &[(f32, f32)]| {
+            points
+                .iter()
+                .map(|(x, y)| format!("{x},{y}"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        match &object.shape {
+            tiled::ObjectShape::Ellipse { .. } => self.line(indent + 1, "<ellipse/>"),
+            tiled::ObjectShape::Point(..) => self.line(indent + 1, "<point/>"),
+            tiled::ObjectShape::Polygon { points } => self.line(
+                indent + 1,
+                &format!(r#"<polygon points="{}"/>"#, points_to_string(points)),
+            ),
+            tiled::ObjectShape::Polyline { points } => self.line(
+                indent + 1,
+                &format!(r#"<polyline points="{}"/>"#, points_to_string(points)),
+            ),
+            _ => {}
+        }
+
+        self.line(indent, "</object>");
+    }
+
+    fn new_objects(&mut self, indent: usize) {
+        self.new_objects_written = true;
+        for object in self.new_objects {
+            let mut line = format!(r#"<object id="{}""#, self.next_object_id);
+            self.next_object_id += 1;
+            if let Some(template) = &object.template {
+                let _ = write!(
+                    line,
+                    r#" template="{}""#,
+                    escape(&self.relative_path(template))
+                );
+            }
+            if !object.class.is_empty() {
+                let _ = write!(line, r#" class="{}""#, escape(&object.class));
+            }
+            let _ = write!(line, r#" x="{}" y="{}""#, object.pos.x, object.pos.y);
+            if object.size != Vec2::ZERO {
+                let _ = write!(
+                    line,
+                    r#" width="{}" height="{}""#,
+                    object.size.x, object.size.y
+                );
+            }
+            line.push('>');
+            self.line(indent, &line);
+            self.properties(indent + 1, &object.properties);
+            self.line(indent, "</object>");
+        }
+    }
+
+    // The global tile ids of a tile layer, row by row from the top.
+    fn data(&mut self, indent: usize, rows: &[Vec<u32>]) {
+        let rows: Vec<String> = rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|gid| gid.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .collect();
+
+        self.line(indent, r#"<data encoding="csv">"#);
+        self.line(0, &rows.join(",\n"));
+        self.line(indent, "</data>");
+    }
+
+    // The XML declaration and the start tag of the map, `next_ids` being the
+    // next free layer and object ids.
+    fn map_start(
+        &mut self,
+        orientation: &tiled::Orientation,
+        size: UVec2,
+        tile_size: UVec2,
+        next_ids: UVec2,
+        background_color: Option<&tiled::Color>,
+    ) {
+        let orientation = match orientation {
+            tiled::Orientation::Orthogonal => "orthogonal",
+            tiled::Orientation::Isometric => "isometric",
+            tiled::Orientation::Staggered => "staggered",
+            tiled::Orientation::Hexagonal => "hexagonal",
+        };
+
+        self.line(0, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+        let mut line = format!(
+            r#"<map version="1.9" orientation="{orientation}" renderorder="right-down" width="{}" height="{}" tilewidth="{}" tileheight="{}" infinite="0" nextlayerid="{}" nextobjectid="{}""#,
+            size.x, size.y, tile_size.x, tile_size.y, next_ids.x, next_ids.y
+        );
+        if let Some(color) = background_color {
+            let _ = write!(line, r#" backgroundcolor="{}""#, color_to_string(color));
+        }
+        line.push('>');
+        self.line(0, &line);
+    }
+
+    fn layers<'map>(
+        &mut self,
+        indent: usize,
+        map: &tiled::Map,
+        layers: impl Iterator<Item = tiled::Layer<'map>>,
+    ) {
+        for layer in layers {
+            let attributes = self.layer_attributes(&layer);
+            match layer.layer_type() {
+                tiled::LayerType::TileLayer(tiled::TileLayer::Finite(layer_data)) => {
+                    self.line(
+                        indent,
+                        &format!(
+                            r#"<layer {attributes} width="{}" height="{}">"#,
+                            layer_data.width(),
+                            layer_data.height()
+                        ),
+                    );
+                    self.properties(indent + 1, &layer.properties);
+
+                    // Rows are written top to bottom, while edits use bevy_ecs_tilemap's positions,
+                    // whose y axis points up on orthogonal maps.
+                    let layer_edits = self.tile_edits.layer(&layer.name);
+                    let mut rows = Vec::new();
+                    for row in 0..layer_data.height() {
+                        let gids: Vec<u32> = (0..layer_data.width())
+                            .map(|x| {
+                                let y = match map.orientation {
+                                    tiled::Orientation::Orthogonal => (map.height - 1) - row,
+                                    _ => row,
+                                };
+                                let pos = UVec2::new(x, y);
+                                edit::layer_tile(map, &layer_data, layer_edits, pos).map_or(
+                                    0,
+                                    |tile| {
+                                        self.gid(
+                                            tile.tileset_index,
+                                            tile.tile_id,
+                                            tile.flip_h,
+                                            tile.flip_v,
+                                            tile.flip_d,
+                                        )
+                                    },
+                                )
+                            })
+                            .collect();
+                        rows.push(gids);
+                    }
+
+                    self.data(indent + 1, &rows);
+                    self.line(indent, "</layer>");
+                }
+                tiled::LayerType::TileLayer(_) => {
+                    log::warn!(
+                        "Skipped exporting layer {}, only finite layers are supported.",
+                        layer.name
+                    );
+                }
+                tiled::LayerType::ObjectLayer(obj_layer) => {
+                    self.line(indent, &format!("<objectgroup {attributes}>"));
+                    self.properties(indent + 1, &layer.properties);
+                    for object in obj_layer.objects() {
+                        let tile_gid = object.get_tile().map(|tile| {
+                            self.gid(
+                                tile.tileset_index(),
+                                tile.id(),
+                                tile.flip_h,
+                                tile.flip_v,
+                                tile.flip_d,
+                            )
+                        });
+                        let object_override = self.objects.get(&object.id());
+                        let template = self.sources.templates.get(&object.id());
+                        self.object(indent + 1, &object, tile_gid, object_override, template);
+                    }
+                    if !self.new_objects_written {
+                        self.new_objects(indent + 1);
+                    }
+                    self.line(indent, "</objectgroup>");
+                }
+                tiled::LayerType::ImageLayer(img_layer) => {
+                    self.line(indent, &format!("<imagelayer {attributes}>"));
+                    self.properties(indent + 1, &layer.properties);
+                    if let Some(image) = &img_layer.image {
+                        self.image(indent + 1, image);
+                    }
+                    self.line(indent, "</imagelayer>");
+                }
+                tiled::LayerType::GroupLayer(group) => {
+                    self.line(indent, &format!("<group {attributes}>"));
+                    self.properties(indent + 1, &layer.properties);
+                    self.layers(indent + 1, map, group.layers());
+                    self.line(indent, "</group>");
+                }
+            }
+        }
+    }
+}
+
+// The contents of a TMX file holding `map` with the runtime changes applied.
+// `dir` is the directory the file is written to.
+pub(crate) fn write_tmx(
+    map: &tiled::Map,
+    sources: &MapSources,
+    dir: &Path,
+    tile_edits: &TileEdits,
+    objects: &HashMap<u32, ObjectOverride>,
+    new_objects: &[NewObject],
+) -> String {
+    let mut first_gids = Vec::new();
+    let mut next_gid = 1;
+    for tileset in map.tilesets().iter() {
+        first_gids.push(next_gid);
+        next_gid += gid_count(tileset);
+    }
+
+    let (mut max_layer_id, mut max_object_id) = (0, 0);
+    max_ids(map.layers(), &mut max_layer_id, &mut max_object_id);
+
+    let mut writer = TmxWriter {
+        tmx: String::new(),
+        dir,
+        first_gids,
+        sources,
+        tile_edits,
+        objects,
+        new_objects,
+        next_object_id: max_object_id + 1,
+        new_objects_written: false,
+    };
+    // Ids for the layer holding the new objects, if it's needed, and the new objects.
+    let new_layer_id = max_layer_id + 1;
+    let max_layer_id = max_layer_id + u32::from(!new_objects.is_empty());
+    let max_object_id = max_object_id + new_objects.len() as u32;
+
+    writer.map_start(
+        &map.orientation,
+        UVec2::new(map.width, map.height),
+        UVec2::new(map.tile_width, map.tile_height),
+        UVec2::new(max_layer_id + 1, max_object_id + 1),
+        map.background_color.as_ref(),
+    );
+    writer.properties(1, &map.properties);
+    for (tileset_index, tileset) in map.tilesets().iter().enumerate() {
+        let first_gid = writer.first_gids[tileset_index];
+        match sources.tilesets.get(tileset_index).cloned().flatten() {
+            Some(source) => {
+                let source = escape(&writer.relative_path(&source));
+                writer.line(
+                    1,
+                    &format!(r#"<tileset firstgid="{first_gid}" source="{source}"/>"#),
+                );
+            }
+            None => writer.tileset(1, first_gid, tileset),
+        }
+    }
+    writer.layers(1, map, map.layers());
+
+    // A layer for the new objects, if the map doesn't have one.
+    if !writer.new_objects_written && !new_objects.is_empty() {
+        writer.line(
+            1,
+            &format!(r#"<objectgroup id="{new_layer_id}" name="objects">"#),
+        );
+        writer.new_objects(2);
+        writer.line(1, "</objectgroup>");
+    }
+    writer.line(0, "</map>");
+
+    writer.tmx
+}
+
+// The contents of a TMX file holding a generated map, written to `dir`.
+pub(crate) fn write_generated_tmx(generated: &GeneratedMap, dir: &Path) -> String {
+    let rules = &generated.rules;
+    let new_objects: Vec<NewObject> = generated
+        .objects
+        .iter()
+        .map(|object| NewObject {
+            class: object.class.clone(),
+            template: None,
+            pos: object.pos,
+            size: object.size,
+            properties: object.properties.clone(),
+        })
+        .collect();
+
+    let sources = MapSources::default();
+    let tile_edits = TileEdits::default();
+    let objects = HashMap::new();
+    let mut writer = TmxWriter {
+        tmx: String::new(),
+        dir,
+        first_gids: vec![1],
+        sources: &sources,
+        tile_edits: &tile_edits,
+        objects: &objects,
+        new_objects: &new_objects,
+        next_object_id: 1,
+        new_objects_written: false,
+    };
+
+    let tile_size = UVec2::new(rules.tile_width, rules.tile_height);
+    writer.map_start(
+        &tiled::Orientation::Orthogonal,
+        UVec2::new(generated.width, generated.height),
+        tile_size,
+        UVec2::new(3, new_objects.len() as u32 + 1),
+        None,
+    );
+
+    // A single tileset, where tiles with collision are covered by a rectangle.
+    writer.line(
+        1,
+        &format!(
+            r#"<tileset firstgid="1" name="generated" tilewidth="{}" tileheight="{}" tilecount="{}" columns="{}">"#,
+            tile_size.x,
+            tile_size.y,
+            rules.tile_count(),
+            rules.columns()
+        ),
+    );
+    let image = writer.relative_path(&Path::new("assets").join(&rules.image));
+    writer.line(
+        2,
+        &format!(
+            r#"<image source="{}" width="{}" height="{}"/>"#,
+            escape(&image),
+            rules.image_width,
+            rules.image_height
+        ),
+    );
+    for rule in rules.tiles.iter().filter(|rule| rule.collision) {
+        writer.line(2, &format!(r#"<tile id="{}">"#, rule.id));
+        writer.line(3, r#"<objectgroup draworder="index">"#);
+        writer.line(
+            4,
+            &format!(
+                r#"<object id="1" x="0" y="0" width="{}" height="{}"/>"#,
+                tile_size.x, tile_size.y
+            ),
+        );
+        writer.line(3, "</objectgroup>");
+        writer.line(2, "</tile>");
+    }
+    writer.line(1, "</tileset>");
+
+    writer.line(
+        1,
+        &format!(
+            r#"<layer id="1" name="ground" width="{}" height="{}">"#,
+            generated.width, generated.height
+        ),
+    );
+    // Global tile ids start at the tileset's firstgid.
+    let rows: Vec<Vec<u32>> = generated
+        .tiles
+        .chunks(generated.width as usize)
+        .map(|row| row.iter().map(|id| id + 1).collect())
+        .collect();
+    writer.data(2, &rows);
+    writer.line(1, "</layer>");
+
+    writer.line(1, r#"<objectgroup id="2" name="objects">"#);
+    writer.new_objects(2);
+    writer.line(1, "</objectgroup>");
+    writer.line(0, "</map>");
+
+    writer.tmx
+}
+
+// The template of the first object of class `class`, so new objects of that class
+// are created like the ones placed in Tiled.
+fn class_template(map: &tiled::Map, sources: &MapSources, class: &str) -> Option<PathBuf> {
+    for flattened_layer in flattened_layers(map, Vec2::ZERO) {
+        let tiled::LayerType::ObjectLayer(obj_layer) = flattened_layer.layer.layer_type() else {
+            continue;
+        };
+
+        for object in obj_layer.objects() {
+            if object.user_type != class {
+                continue;
+            }
+            if let Some(instance) = sources.templates.get(&object.id()) {
+                return Some(instance.template.clone());
+            }
+        }
+    }
+
+    None
+}
+
+// The position of `world_pos` in the Tiled pixel coordinates of `map`, placed at `map_offset`.
+// None if it's outside of the map.
+fn tiled_pos(map: &tiled::Map, map_offset: Vec2, world_pos: Vec2) -> Option<Vec2> {
+    let local = world_pos - (map_top_left(map) + map_offset);
+    let pos = Vec2::new(local.x, -local.y);
+    let size = Vec2::new(
+        (map.width * map.tile_width) as f32,
+        (map.height * map.tile_height) as f32,
+    );
+
+    (pos.cmpge(Vec2::ZERO).all() && pos.cmplt(size).all()).then_some(pos)
+}
+
+pub fn export_maps(
+    mut events: EventReader<ExportMap>,
+    maps: Res<Assets<TiledMap>>,
+    class_registry: Res<TiledClassRegistry>,
+    map_q: Query<(&Handle<TiledMap>, &Transform, &TileEdits)>,
+    object_q: Query<(&TiledObject, &Transform, Option<&MapNpc>)>,
+    // Prototypes spawned at runtime, other than the player and the children of other entities.
+    prototype_q: Query<
+        (&PrototypeName, &Transform),
+        (Without<TiledObject>, Without<Parent>, Without<Controllable>),
+    >,
+    npc_pool: Res<NpcPool>,
+    stashed_npcs: Res<StashedNpcs>,
+    signs_pool: Res<SignsPool>,
+) {
+    for ExportMap { map, path } in events.iter() {
+        let Ok((map_handle, map_transform, tile_edits)) = map_q.get(*map) else {
+            log::warn!("Can't export {:?}, it isn't a map", map);
+            continue;
+        };
+        let Some(tiled_map) = maps.get(map_handle) else {
+            log::warn!("Can't export {:?}, its map isn't loaded yet", map);
+            continue;
+        };
+        let map_offset = map_transform.translation.truncate();
+
+        let mut objects = HashMap::new();
+        for (tiled_object, transform, map_npc) in object_q.iter() {
+            if tiled_object.map != *map {
+                continue;
+            }
+
+            objects.insert(
+                tiled_object.id,
+                ObjectOverride {
+                    offset: (transform.translation - tiled_object.spawn_pos).truncate(),
+                    properties: map_npc.map(|npc| npc.properties.clone()),
+                },
+            );
+        }
+
+        // NPCs which aren't spawned right now.
+        for npc in npc_pool.npcs.iter().chain(stashed_npcs.npcs.iter()) {
+            if npc.object.map != *map {
+                continue;
+            }
+
+            objects.insert(
+                npc.object.id,
+                ObjectOverride {
+                    offset: (npc.pos - npc.object.spawn_pos).truncate(),
+                    properties: Some(npc.properties.clone()),
+                },
+            );
+        }
+
+        let mut new_objects = Vec::new();
+        let runtime_signs = signs_pool.signs.get(map).into_iter().flatten();
+        for sign in runtime_signs.filter(|sign| sign.object_id.is_none()) {
+            let Some(pos) = tiled_pos(&tiled_map.map, map_offset, Vec2::new(sign.x, sign.y)) else {
+                continue;
+            };
+
+            let mut properties = sign.properties.clone();
+            properties.insert("id".to_string(), PropertyValue::IntValue(sign.id as i32));
+            new_objects.push(NewObject {
+                class: sign.class.clone(),
+                template: class_template(&tiled_map.map, &tiled_map.sources, &sign.class),
+                pos,
+                size: Vec2::ZERO,
+                properties,
+            });
+        }
+
+        // Prototypes are written as NPCs, which spawn their prototype when the map is loaded.
+        match class_registry.pooled_class(PooledObject::Npc) {
+            Some(npc_class) => {
+                let template = class_template(&tiled_map.map, &tiled_map.sources, npc_class);
+                for (name, transform) in prototype_q.iter() {
+                    let world_pos = transform.translation;
+                    let Some(pos) = tiled_pos(&tiled_map.map, map_offset, world_pos.truncate())
+                    else {
+                        continue;
+                    };
+
+                    let mut properties = Properties::new();
+                    properties.insert("id".to_string(), PropertyValue::StringValue(name.0.clone()));
+                    // Without a template, nothing else gives the NPC its z.
+                    if template.is_none() {
+                        properties
+                            .insert("z".to_string(), PropertyValue::IntValue(world_pos.z as i32));
+                    }
+                    new_objects.push(NewObject {
+                        class: npc_class.to_string(),
+                        template: template.clone(),
+                        pos,
+                        size: Vec2::ZERO,
+                        properties,
+                    });
+                }
+            }
+            None if !prototype_q.is_empty() => {
+                log::warn!("Not exporting the prototypes spawned on {:?}: no Tiled class is registered for NPCs", map);
+            }
+            None => {}
+        }
+
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let tmx = write_tmx(
+            &tiled_map.map,
+            &tiled_map.sources,
+            dir,
+            tile_edits,
+            &objects,
+            &new_objects,
+        );
+        match std::fs::write(path, tmx) {
+            Ok(()) => log::info!("Exported map to {}", path.display()),
+            Err(e) => log::error!("Could not export map to {}: {e}", path.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+
+    use super::*;
+    use crate::tiled::{GeneratorRules, MapGenerator};
+
+    const MAP_PATH: &str = "assets/map/simple.tmx";
+
+    fn object_layers(map: &tiled::Map) -> Vec<Vec<tiled::ObjectData>> {
+        flattened_layers(map, Vec2::ZERO)
+            .iter()
+            .filter_map(|flattened_layer| match flattened_layer.layer.layer_type() {
+                tiled::LayerType::ObjectLayer(obj_layer) => Some(
+                    obj_layer
+                        .objects()
+                        .map(|object| (*object).clone())
+                        .collect(),
+                ),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn simple_map_round_trip() {
+        let map = prototyp::tmx::load_map(MAP_PATH).unwrap();
+        let sources = MapSources::parse(
+            &std::fs::read_to_string(MAP_PATH).unwrap(),
+            Path::new(MAP_PATH),
+        );
+
+        let mut properties = Properties::new();
+        properties.insert(
+            "id".to_string(),
+            PropertyValue::StringValue("patient".to_string()),
+        );
+        properties.insert(
+            "note".to_string(),
+            PropertyValue::StringValue(r#"<"a" & 'b'>"#.to_string()),
+        );
+        let new_object = NewObject {
+            class: prototyp::map_classes::NPC.to_string(),
+            template: Some(PathBuf::from("assets/map/templates/npc.tx")),
+            pos: Vec2::new(32.0, 64.0),
+            size: Vec2::ZERO,
+            properties,
+        };
+
+        let tmx = write_tmx(
+            &map,
+            &sources,
+            Path::new("assets/map"),
+            &TileEdits::default(),
+            &HashMap::new(),
+            &[new_object],
+        );
+        assert!(tmx.contains(r#"<tileset firstgid="1" source="bg.tsx"/>"#));
+        assert!(tmx.contains(r#"template="templates/npc.tx""#));
+
+        let exported = prototyp::tmx::load_map_from(&tmx, Path::new(MAP_PATH)).unwrap();
+        let exported_sources = MapSources::parse(&tmx, Path::new(MAP_PATH));
+        assert_eq!(exported_sources.tilesets, sources.tilesets);
+        for (id, instance) in sources.templates.iter() {
+            assert_eq!(exported_sources.templates.get(id), Some(instance));
+        }
+
+        // Same layers, with the same tiles.
+        let layers = flattened_layers(&map, Vec2::ZERO);
+        let exported_layers = flattened_layers(&exported, Vec2::ZERO);
+        assert_eq!(layers.len(), exported_layers.len());
+        for (layer, exported_layer) in layers.iter().zip(exported_layers.iter()) {
+            assert_eq!(layer.layer.name, exported_layer.layer.name);
+            match (layer.layer.layer_type(), exported_layer.layer.layer_type()) {
+                (
+                    tiled::LayerType::TileLayer(tiled::TileLayer::Finite(layer_data)),
+                    tiled::LayerType::TileLayer(tiled::TileLayer::Finite(exported_data)),
+                ) => {
+                    for x in 0..map.width {
+                        for y in 0..map.height {
+                            let pos = UVec2::new(x, y);
+                            assert_eq!(
+                                edit::layer_tile(&map, &layer_data, None, pos),
+                                edit::layer_tile(&exported, &exported_data, None, pos),
+                                "tile {pos} of layer {}",
+                                layer.layer.name
+                            );
+                        }
+                    }
+                }
+                (tiled::LayerType::ObjectLayer(_), tiled::LayerType::ObjectLayer(_))
+                | (tiled::LayerType::ImageLayer(_), tiled::LayerType::ImageLayer(_))
+                | (tiled::LayerType::GroupLayer(_), tiled::LayerType::GroupLayer(_)) => {}
+                _ => panic!("layer {} changed its type", layer.layer.name),
+            }
+        }
+
+        // Same objects, plus the new one in the first object layer.
+        let objects = object_layers(&map);
+        let mut exported_objects = object_layers(&exported);
+        let new_object = exported_objects[0].pop().unwrap();
+        assert_eq!(objects, exported_objects);
+        let max_id = objects
+            .iter()
+            .flatten()
+            .map(|object| object.id())
+            .max()
+            .unwrap();
+        assert_eq!(new_object.id(), max_id + 1);
+        assert_eq!(new_object.user_type, prototyp::map_classes::NPC);
+        assert_eq!((new_object.x, new_object.y), (32.0, 64.0));
+        assert_eq!(
+            new_object.properties.get("note"),
+            Some(&PropertyValue::StringValue(r#"<"a" & 'b'>"#.to_string()))
+        );
+        // From the template.
+        assert_eq!(
+            new_object.properties.get("z"),
+            Some(&PropertyValue::IntValue(10))
+        );
+    }
+
+    #[test]
+    fn generated_map_round_trip() {
+        let rules =
+            GeneratorRules::load(Path::new("assets/map/random_tileset.rules.yaml")).unwrap();
+        let generator = MapGenerator {
+            width: 8,
+            height: 6,
+            seed: 1,
+            npcs: vec![r#"<"a" & 'b'>"#.to_string()],
+            signs: vec![3],
+        };
+        let generated = generator.generate(&rules).unwrap();
+
+        let map = tiled::Loader::new()
+            .load_tmx_map_from(
+                BufReader::new(generated.to_tmx().as_bytes()),
+                "assets/map/generated.tmx",
+            )
+            .unwrap();
+        assert_eq!(
+            map.tilesets()[0].image.as_ref().unwrap().source,
+            Path::new("assets/map/random_tileset.png")
+        );
+
+        let tiled::LayerType::TileLayer(tiled::TileLayer::Finite(ground)) =
+            map.get_layer(0).unwrap().layer_type()
+        else {
+            panic!("ground isn't a finite tile layer");
+        };
+        for (index, id) in generated.tiles.iter().enumerate() {
+            let (x, y) = (
+                index as u32 % generated.width,
+                index as u32 / generated.width,
+            );
+            assert_eq!(ground.get_tile(x as i32, y as i32).unwrap().id(), *id);
+        }
+
+        let objects = &object_layers(&map)[0];
+        assert_eq!(objects.len(), 2);
+        assert_eq!(
+            objects[0].properties.get("id"),
+            Some(&PropertyValue::StringValue(r#"<"a" & 'b'>"#.to_string()))
+        );
+        assert_eq!(
+            objects[1].properties.get("id"),
+            Some(&PropertyValue::IntValue(3))
+        );
+    }
+}
@@ -0,0 +1,379 @@
+use std::{
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use bevy::prelude::*;
+use prototyp::map_classes;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use serde::Deserialize;
+
+use super::{export, TiledMap};
+
+// Number of times the generator starts over after running into a contradiction.
+const MAX_ATTEMPTS: u32 = 20;
+
+/// The tileset of a generated map along with the rules of which tiles may be placed
+/// next to each other. Usually loaded from a YAML file, see `load`.
+#[derive(Deserialize, Clone, Debug)]
+pub struct GeneratorRules {
+    // Path of the tileset image, relative to assets/.
+    pub image: String,
+    pub image_width: u32,
+    pub image_height: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub tiles: Vec<TileRule>,
+    // Prototype names of the NPCs and ids of the signs to place on generated maps.
+    #[serde(default)]
+    pub npcs: Vec<String>,
+    #[serde(default)]
+    pub signs: Vec<u32>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct TileRule {
+    // Id of the tile within the tileset image, counted row by row.
+    pub id: u32,
+    // How often the tile is picked relative to the others.
+    #[serde(default = "default_weight")]
+    pub weight: f32,
+    // Tiles with collision get a collider covering the whole tile
+    // and NPCs and signs aren't placed on them.
+    #[serde(default)]
+    pub collision: bool,
+    // The tiles allowed right of and below this one. Any tile if not given.
+    #[serde(default)]
+    pub right: Option<Vec<u32>>,
+    #[serde(default)]
+    pub down: Option<Vec<u32>>,
+}
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+impl GeneratorRules {
+    pub fn load(filename: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(filename)
+            .map_err(|e| format!("Could not read {}: {e}", filename.display()))?;
+        serde_yaml::from_str(&contents)
+            .map_err(|e| format!("Could not parse {}: {e}", filename.display()))
+    }
+
+    pub(crate) fn columns(&self) -> u32 {
+        self.image_width / self.tile_width
+    }
+
+    pub(crate) fn tile_count(&self) -> u32 {
+        self.columns() * (self.image_height / self.tile_height)
+    }
+
+    // Whether rule `b` may be placed right of rule `a`.
+    fn allows_right(&self, a: usize, b: usize) -> bool {
+        self.tiles[a]
+            .right
+            .as_ref()
+            .map_or(true, |right| right.contains(&self.tiles[b].id))
+    }
+
+    // Whether rule `b` may be placed below rule `a`.
+    fn allows_down(&self, a: usize, b: usize) -> bool {
+        self.tiles[a]
+            .down
+            .as_ref()
+            .map_or(true, |down| down.contains(&self.tiles[b].id))
+    }
+}
+
+/// Generates maps with wave function collapse: the cell with the fewest possible tiles
+/// is filled with one of them at random, which in turn limits the tiles possible next to
+/// it, until every cell is filled. The same seed always produces the same map.
+pub struct MapGenerator {
+    pub width: u32,
+    pub height: u32,
+    pub seed: u64,
+    // Prototype names of the NPCs to place.
+    pub npcs: Vec<String>,
+    // Ids of the signs to place.
+    pub signs: Vec<u32>,
+}
+
+pub struct GeneratedObject {
+    pub class: String,
+    // Position in Tiled's pixel coordinates, i.e y points down.
+    pub pos: Vec2,
+    pub size: Vec2,
+    pub properties: tiled::Properties,
+}
+
+/// A generated map in the shape of a TMX map with a single tileset,
+/// a tile layer called `ground` and an object layer called `objects`.
+pub struct GeneratedMap {
+    pub rules: GeneratorRules,
+    pub width: u32,
+    pub height: u32,
+    // Tile ids in Tiled's row order, i.e row 0 is the top row of the map.
+    pub tiles: Vec<u32>,
+    pub objects: Vec<GeneratedObject>,
+}
+
+impl MapGenerator {
+    pub fn generate(&self, rules: &GeneratorRules) -> Result<GeneratedMap, String> {
+        if rules.tiles.is_empty() {
+            return Err("The rules don't have any tiles".to_string());
+        }
+
+        if let Some(rule) = rules.tiles.iter().find(|rule| rule.id >= rules.tile_count()) {
+            return Err(format!("Tile {} is outside of the tileset", rule.id));
+        }
+
+        let mut rng = StdRng::seed_from_u64(self.seed);
+
+        let mut cells = None;
+        for attempt in 0..MAX_ATTEMPTS {
+            cells = collapse(rules, self.width, self.height, &mut rng);
+            if cells.is_some() {
+                break;
+            }
+
+            log::info!("Map generation ran into a contradiction, attempt {}", attempt + 1);
+        }
+
+        let Some(cells) = cells else {
+            return Err(format!(
+                "The rules didn't produce a map after {MAX_ATTEMPTS} attempts"
+            ));
+        };
+
+        let mut walkable: Vec<usize> = (0..cells.len())
+            .filter(|index| !rules.tiles[cells[*index]].collision)
+            .collect();
+        walkable.shuffle(&mut rng);
+
+        let tile_size = Vec2::new(rules.tile_width as f32, rules.tile_height as f32);
+        let cell_center = |index: usize| {
+            let x = index as u32 % self.width;
+            let y = index as u32 / self.width;
+            (Vec2::new(x as f32, y as f32) + Vec2::splat(0.5)) * tile_size
+        };
+
+        // Each object gets a walkable tile of its own.
+        let mut walkable = walkable.into_iter();
+        let mut next_pos = || {
+            walkable.next().map(cell_center).ok_or_else(|| {
+                format!(
+                    "There aren't enough walkable tiles for {} NPCs and {} signs",
+                    self.npcs.len(),
+                    self.signs.len()
+                )
+            })
+        };
+        let mut objects = Vec::new();
+        for npc in self.npcs.iter() {
+            objects.push(GeneratedObject {
+                class: map_classes::NPC.to_string(),
+                pos: next_pos()?,
+                size: Vec2::ZERO,
+                properties: tiled::Properties::from([
+                    ("id".to_string(), tiled::PropertyValue::StringValue(npc.clone())),
+                    ("z".to_string(), tiled::PropertyValue::IntValue(10)),
+                ]),
+            });
+        }
+
+        for sign in self.signs.iter() {
+            objects.push(GeneratedObject {
+                class: map_classes::SIGN.to_string(),
+                pos: next_pos()?,
+                size: Vec2::new(20.0, 18.0),
+                properties: tiled::Properties::from([
+                    ("id".to_string(), tiled::PropertyValue::IntValue(*sign as i32)),
+                    ("z".to_string(), tiled::PropertyValue::IntValue(100)),
+                ]),
+            });
+        }
+
+        Ok(GeneratedMap {
+            rules: rules.clone(),
+            width: self.width,
+            height: self.height,
+            tiles: cells.iter().map(|rule| rules.tiles[*rule].id).collect(),
+            objects,
+        })
+    }
+}
+
+// Fills a map with the indices of tile rules. None if a cell ran out of possible tiles.
+fn collapse(rules: &GeneratorRules, width: u32, height: u32, rng: &mut StdRng) -> Option<Vec<usize>> {
+    let tile_cnt = rules.tiles.len();
+    let (width, height) = (width as usize, height as usize);
+
+    // The rules still possible for each cell, in Tiled's row order.
+    let mut cells = vec![vec![true; tile_cnt]; width * height];
+
+    loop {
+        // The undecided cell with the fewest possibilities, picking randomly among ties.
+        let mut lowest = usize::MAX;
+        let mut candidates = Vec::new();
+        for (index, cell) in cells.iter().enumerate() {
+            let possible = cell.iter().filter(|p| **p).count();
+            if possible <= 1 || possible > lowest {
+                continue;
+            }
+
+            if possible < lowest {
+                lowest = possible;
+                candidates.clear();
+            }
+            candidates.push(index);
+        }
+
+        let Some(&index) = candidates.choose(rng) else {
+            break;
+        };
+
+        let options: Vec<usize> = (0..tile_cnt).filter(|rule| cells[index][*rule]).collect();
+        let picked = *options
+            .choose_weighted(rng, |rule| rules.tiles[*rule].weight.max(f32::EPSILON))
+            .ok()?;
+        for (rule, possible) in cells[index].iter_mut().enumerate() {
+            *possible = rule == picked;
+        }
+
+        // Removes the rules the neighbors no longer allow, spreading out from the picked cell.
+        let mut stack = vec![index];
+        while let Some(index) = stack.pop() {
+            let (x, y) = (index % width, index / width);
+            let mut neighbors = Vec::with_capacity(4);
+            if x + 1 < width {
+                neighbors.push((index + 1, true, false));
+            }
+            if x > 0 {
+                neighbors.push((index - 1, true, true));
+            }
+            if y + 1 < height {
+                neighbors.push((index + width, false, false));
+            }
+            if y > 0 {
+                neighbors.push((index - width, false, true));
+            }
+
+            for (neighbor, horizontal, reversed) in neighbors {
+                let mut changed = false;
+                for rule in 0..tile_cnt {
+                    if !cells[neighbor][rule] {
+                        continue;
+                    }
+
+                    let supported = (0..tile_cnt).any(|own| {
+                        if !cells[index][own] {
+                            return false;
+                        }
+
+                        let (a, b) = if reversed { (rule, own) } else { (own, rule) };
+                        if horizontal {
+                            rules.allows_right(a, b)
+                        } else {
+                            rules.allows_down(a, b)
+                        }
+                    });
+
+                    if !supported {
+                        cells[neighbor][rule] = false;
+                        changed = true;
+                    }
+                }
+
+                if !cells[neighbor].iter().any(|p| *p) {
+                    return None;
+                }
+
+                if changed {
+                    stack.push(neighbor);
+                }
+            }
+        }
+    }
+
+    cells
+        .iter()
+        .map(|cell| cell.iter().position(|p| *p))
+        .collect()
+}
+
+impl GeneratedMap {
+    // The map is placed next to the tileset image, so its path resolves.
+    fn path(&self) -> PathBuf {
+        Path::new("assets")
+            .join(&self.rules.image)
+            .with_file_name("generated.tmx")
+    }
+
+    /// The map as the contents of a TMX file placed next to the tileset image.
+    pub fn to_tmx(&self) -> String {
+        let path = self.path();
+        export::write_generated_tmx(self, path.parent().unwrap_or_else(|| Path::new("")))
+    }
+
+    /// Loads the generated map like a TMX file, so it can be added to the
+    /// `TiledMap` assets and spawned with a `TiledMapBundle`.
+    pub fn to_tiled_map(&self, asset_server: &AssetServer) -> Result<TiledMap> {
+        let mut loader = tiled::Loader::new();
+        let map = loader
+            .load_tmx_map_from(BufReader::new(self.to_tmx().as_bytes()), self.path())
+            .map_err(|e| anyhow::anyhow!("Could not load generated map: {e}"))?;
+
+        Ok(TiledMap::new(map, |image_path| asset_server.load(image_path)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn objects_need_walkable_tiles() {
+        let rules = GeneratorRules {
+            image: "map/random_tileset.png".to_string(),
+            image_width: 64,
+            image_height: 32,
+            tile_width: 32,
+            tile_height: 32,
+            tiles: vec![
+                TileRule {
+                    id: 0,
+                    weight: 1.0,
+                    collision: false,
+                    right: Some(vec![1]),
+                    down: None,
+                },
+                TileRule {
+                    id: 1,
+                    weight: 1.0,
+                    collision: true,
+                    right: Some(vec![0]),
+                    down: None,
+                },
+            ],
+            npcs: Vec::new(),
+            signs: Vec::new(),
+        };
+
+        // Every other column is walkable.
+        let mut generator = MapGenerator {
+            width: 2,
+            height: 2,
+            seed: 0,
+            npcs: vec!["patient".to_string()],
+            signs: vec![1],
+        };
+        let generated = generator.generate(&rules).unwrap();
+        assert_eq!(generated.objects.len(), 2);
+        assert_ne!(generated.objects[0].pos, generated.objects[1].pos);
+
+        generator.signs.push(2);
+        assert!(generator.generate(&rules).is_err());
+    }
+}
@@ -0,0 +1,106 @@
+use bevy::{prelude::*, sprite::Anchor};
+use tiled::PropertyValue;
+
+use super::{asset_path, map_top_left, parallax::Parallax, FlattenedLayer};
+
+// Tiled 0.10 doesn't expose the image layer repeat flags,
+// so they are read from the `repeat_x` and `repeat_y` bool properties.
+fn repeats(layer: &FlattenedLayer, name: &str) -> bool {
+    matches!(layer.properties.get(name), Some(PropertyValue::BoolValue(true)))
+}
+
+// Indices of the image copies needed to cover [min, max] when repeating.
+fn repeat_range(repeat: bool, start: f32, size: f32, min: f32, max: f32) -> std::ops::RangeInclusive<i32> {
+    if !repeat || size <= 0.0 {
+        return 0..=0;
+    }
+
+    let first = ((min - start) / size).floor() as i32;
+    let last = ((max - start) / size).ceil() as i32;
+    first..=last
+}
+
+pub(crate) fn spawn_image_layer(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    map: &tiled::Map,
+    flattened_layer: &FlattenedLayer,
+    image_layer: &tiled::ImageLayer,
+) -> Option<Entity> {
+    let image = image_layer.image.as_ref()?;
+    let texture: Handle<Image> = asset_server.load(asset_path(&image.source));
+
+    let z = match flattened_layer.properties.get("z") {
+        Some(PropertyValue::IntValue(z)) => *z as f32,
+        _ => 0.0,
+    };
+
+    let map_px = Vec2::new(
+        (map.width * map.tile_width) as f32,
+        (map.height * map.tile_height) as f32,
+    );
+    let map_top_left = map_top_left(map) + flattened_layer.map_offset;
+    let base = (map_top_left + Vec2::new(flattened_layer.offset_x, -flattened_layer.offset_y))
+        .extend(z);
+
+    // Repeated images cover the map with an image of margin on each side,
+    // so parallax scrolling doesn't reveal the edges right away.
+    let image_size = Vec2::new(image.width as f32, image.height as f32);
+    let columns = repeat_range(
+        repeats(flattened_layer, "repeat_x"),
+        base.x,
+        image_size.x,
+        map_top_left.x - image_size.x,
+        map_top_left.x + map_px.x + image_size.x,
+    );
+    let rows = repeat_range(
+        repeats(flattened_layer, "repeat_y"),
+        -base.y,
+        image_size.y,
+        -map_top_left.y - image_size.y,
+        -map_top_left.y + map_px.y + image_size.y,
+    );
+
+    let color = flattened_layer.color();
+
+    let layer_entity = commands
+        .spawn(SpatialBundle {
+            // Hidden layers are still spawned, so they can be shown at runtime.
+            visibility: Visibility {
+                is_visible: flattened_layer.visible,
+            },
+            ..SpatialBundle::from_transform(Transform::from_translation(base))
+        })
+        .insert(Name::new(flattened_layer.layer.name.clone()))
+        .with_children(|parent| {
+            for row in rows {
+                for column in columns.clone() {
+                    parent.spawn(SpriteBundle {
+                        sprite: Sprite {
+                            color,
+                            anchor: Anchor::TopLeft,
+                            ..default()
+                        },
+                        texture: texture.clone(),
+                        transform: Transform::from_xyz(
+                            column as f32 * image_size.x,
+                            -row as f32 * image_size.y,
+                            0.0,
+                        ),
+                        ..default()
+                    });
+                }
+            }
+        })
+        .id();
+
+    if Parallax::is_needed(flattened_layer.parallax) {
+        commands.entity(layer_entity).insert(Parallax {
+            factor: flattened_layer.parallax,
+            origin: map_top_left,
+            base,
+        });
+    }
+
+    Some(layer_entity)
+}
@@ -0,0 +1,992 @@
+use std::{collections::HashMap, path::{Path, PathBuf}};
+
+use bevy::prelude::{Vec2, AssetServer, Color, CoreStage, IntoSystemDescriptor, Name, SpatialBundle, Visibility};
+use bevy::transform::TransformSystem;
+use bevy::{
+    asset::{AssetLoader, AssetPath, LoadedAsset},
+    log,
+    prelude::{
+        AddAsset, Added, AssetEvent, Assets, Bundle, Children, Commands, Component,
+        DespawnRecursiveExt, Entity, EventReader, EventWriter, GlobalTransform, Handle, Image, Plugin, Query,
+        RemovedComponents, Res, ResMut, Transform, Vec3, With,
+    },
+    reflect::TypeUuid,
+};
+use bevy_ecs_tilemap::prelude::*;
+
+use anyhow::Result;
+use tiled::{Properties, PropertyValue};
+
+use crate::components::MapNpc;
+use crate::resources::{SignData, SignsPool, TilesProperties, NpcPool, NpcData, NpcState, LogicLayers, MapLogicLayers, StashedNpcs};
+
+mod chunks;
+mod classes;
+mod collision;
+mod edit;
+mod export;
+mod generate;
+mod image_layer;
+mod navigation;
+mod object_layer;
+mod parallax;
+mod query;
+mod sources;
+mod sprite_tiles;
+mod tile_layer;
+mod world;
+
+pub use chunks::{ChunkStreaming, MapChunks, TileRegion};
+pub use classes::{InsertTiledClass, PooledObject, RegisterTiledClass, TiledClassRegistry};
+pub use collision::TileOrientation;
+pub use edit::{EditTile, MapTile, TileEdit, TileEdits};
+pub use export::ExportMap;
+pub use generate::{GeneratedMap, GeneratedObject, GeneratorRules, MapGenerator, TileRule};
+pub use navigation::{NavGrid, RebuildNavGrid};
+pub use parallax::Parallax;
+pub use query::{TileInfo, TileQuery};
+pub use sources::{MapSources, TemplateInstance};
+pub use world::{TiledWorld, TiledWorldBundle, WorldMap};
+
+#[derive(Default)]
+pub struct TiledMapPlugin;
+
+impl Plugin for TiledMapPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_asset::<TiledMap>()
+            .add_asset::<TiledWorld>()
+            .add_asset_loader(TiledLoader)
+            .init_resource::<TiledClassRegistry>()
+            .init_resource::<ChunkStreaming>()
+            .init_resource::<NavGrid>()
+            .add_event::<RebuildNavGrid>()
+            .add_event::<EditTile>()
+            .add_event::<ExportMap>()
+            .add_system(world::process_loaded_worlds.before(process_loaded_maps))
+            .add_system(edit::apply_tile_edits.before(process_loaded_maps))
+            .add_system(process_loaded_maps)
+            .add_system_to_stage(CoreStage::PostUpdate, forget_despawned_maps)
+            .add_system(chunks::stream_chunks.after(process_loaded_maps))
+            .add_system(navigation::rebuild_nav_grid.after(process_loaded_maps))
+            .add_system(export::export_maps.after(process_loaded_maps))
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                parallax::apply_parallax.before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+#[derive(TypeUuid)]
+#[uuid = "e51081d0-6168-4881-a1c6-4249b2000d7f"]
+pub struct TiledMap {
+    pub map: tiled::Map,
+
+    pub tilemap_textures: HashMap<usize, TilemapTexture>,
+
+    // The offset into the tileset_images for each tile id within each tileset.
+    pub tile_image_offsets: HashMap<(usize, tiled::TileId), u32>,
+
+    // The external tilesets and templates of the TMX file, for exporting.
+    pub sources: MapSources,
+}
+
+impl TiledMap {
+    // Collects the textures of the map's tilesets. `load_image` returns the handle
+    // of an image given its asset path.
+    pub(crate) fn new(map: tiled::Map, mut load_image: impl FnMut(AssetPath<'static>) -> Handle<Image>) -> Self {
+        let mut tilemap_textures = HashMap::default();
+        let mut tile_image_offsets = HashMap::default();
+
+        for (tileset_index, tileset) in map.tilesets().iter().enumerate() {
+            let tilemap_texture = match &tileset.image {
+                None => {
+                    let mut tile_images: Vec<Handle<Image>> = Vec::new();
+                    for (tile_id, tile) in tileset.tiles() {
+                        if let Some(img) = &tile.image {
+                            let image_path = AssetPath::new(asset_path(&img.source), None);
+                            log::info!("Loading tile image from {image_path:?} as image ({tileset_index}, {tile_id})");
+                            let texture = load_image(image_path);
+                            tile_image_offsets
+                                .insert((tileset_index, tile_id), tile_images.len() as u32);
+                            tile_images.push(texture);
+                        }
+                    }
+
+                    // A TilemapTexture::Vector requires all images to be the same size.
+                    // Tiles of other collections are spawned as individual sprites.
+                    if !has_uniform_tile_images(tileset) {
+                        log::info!("Tileset {} has images of different sizes. Its tiles will be spawned as sprites.", tileset.name);
+                        continue;
+                    }
+
+                    TilemapTexture::Vector(tile_images)
+                }
+                Some(img) => {
+                    let image_path = AssetPath::new(asset_path(&img.source), None);
+                    TilemapTexture::Single(load_image(image_path))
+                }
+            };
+
+            tilemap_textures.insert(tileset_index, tilemap_texture);
+        }
+
+        Self {
+            map,
+            tilemap_textures,
+            tile_image_offsets,
+            sources: MapSources::default(),
+        }
+    }
+}
+
+// Stores a list of tiled layers.
+#[derive(Component, Default)]
+pub struct TiledLayersStorage {
+    pub storage: HashMap<u32, Entity>,
+}
+
+// Identifies the Tiled layer a tilemap entity was spawned from.
+#[derive(Component)]
+pub struct TiledLayer {
+    // The map entity the layer belongs to.
+    pub map: Entity,
+    pub name: String,
+    pub tileset_index: usize,
+    // Position of the tilemap's bottom-left tile on the map.
+    // Tilemaps of a streamed map only cover a single chunk.
+    pub origin: TilePos,
+    // The map row the tiles are on, for the tilemaps of y-sorted layers
+    // which are split by row. Their storage still covers the whole region.
+    pub row: Option<u32>,
+}
+
+// The id of a tile within its tileset. Tiles of image collections use
+// a different texture index, as their images are packed together.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct TiledTileId(pub u32);
+
+// The Tiled object an entity was spawned from, along with where it was spawned.
+// Used to write the objects back to the map when it's exported.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct TiledObject {
+    pub map: Entity,
+    pub id: u32,
+    pub spawn_pos: Vec3,
+}
+
+// The position on the map of a tile spawned as a sprite.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct TiledSpriteTile(pub TilePos);
+
+#[derive(Component, Default)]
+pub struct TiledMapBundleMarker;
+
+#[derive(Default, Bundle)]
+pub struct TiledMapBundle {
+    pub tiled_map: Handle<TiledMap>,
+    pub storage: TiledLayersStorage,
+    pub chunks: MapChunks,
+    pub tile_edits: TileEdits,
+    // The map is centered around its translation.
+    pub transform: Transform,
+    pub global_transform: GlobalTransform,
+
+    pub marker: TiledMapBundleMarker,
+}
+
+pub struct TiledLoader;
+
+impl AssetLoader for TiledLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut bevy::asset::LoadContext,
+    ) -> bevy::asset::BoxedFuture<'a, Result<()>> {
+        Box::pin(async move {
+            if load_context.path().extension().map_or(false, |ext| ext == "world") {
+                return world::load_world(bytes, load_context);
+            }
+
+            // We need to give the assets path to the Tiled loader as it doesn't know about bevy
+            // Object templates are resolved relative to the map before it's handed to the Tiled loader.
+            let path = Path::new("assets").join(load_context.path());
+
+            let tmx = String::from_utf8_lossy(bytes);
+            let map = prototyp::tmx::load_map_from(&tmx, &path).map_err(|e| anyhow::anyhow!(e))?;
+
+            let mut dependencies = Vec::new();
+            let mut asset_map = TiledMap::new(map, |image_path| {
+                let texture: Handle<Image> = load_context.get_handle(image_path.clone());
+                dependencies.push(image_path);
+                texture
+            });
+            asset_map.sources = MapSources::parse(&tmx, &path);
+
+            log::info!("Loaded map: {}", load_context.path().display());
+
+            let loaded_asset = LoadedAsset::new(asset_map);
+            load_context.set_default_asset(loaded_asset.with_dependencies(dependencies));
+
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        static EXTENSIONS: &[&str] = &["tmx", "world"];
+        EXTENSIONS
+    }
+}
+
+fn has_uniform_tile_images(tileset: &tiled::Tileset) -> bool {
+    let mut sizes = tileset
+        .tiles()
+        .filter_map(|(_, tile)| tile.image.as_ref().map(|img| (img.width, img.height)));
+
+    match sizes.next() {
+        Some(first) => sizes.all(|size| size == first),
+        None => true,
+    }
+}
+
+// Paths resolved by the tiled crate are relative to the working directory,
+// while the asset server expects them relative to assets/.
+pub(crate) fn asset_path(path: &Path) -> PathBuf {
+    path.strip_prefix("assets")
+        .unwrap_or(path)
+        .to_path_buf()
+}
+
+/// A non-group layer along with the offset, visibility, opacity, tint, parallax
+/// and properties it inherits from the group layers it's nested in.
+pub struct FlattenedLayer<'map> {
+    pub layer: tiled::Layer<'map>,
+    // Where the map is placed in the world, see `TiledMapBundle::transform`.
+    pub map_offset: Vec2,
+    pub offset_x: f32,
+    pub offset_y: f32,
+    pub visible: bool,
+    pub opacity: f32,
+    pub tint: Color,
+    pub parallax: Vec2,
+    pub properties: Properties,
+}
+
+impl FlattenedLayer<'_> {
+    // The transform of the layer's tilemaps. Maps are centered around their offset.
+    pub fn tilemap_transform(&self, map: &tiled::Map, z: f32) -> Transform {
+        let map_size = TilemapSize {
+            x: map.width,
+            y: map.height,
+        };
+
+        let grid_size = TilemapGridSize {
+            x: map.tile_width as f32,
+            y: map.tile_height as f32,
+        };
+
+        Transform::from_translation(self.map_offset.extend(0.0))
+            * get_tilemap_center_transform(&map_size, &grid_size, &TilemapType::Square, z)
+            * Transform::from_xyz(self.offset_x, -self.offset_y, 0.0)
+    }
+
+    // The color tiles and images of this layer are multiplied with.
+    pub fn color(&self) -> Color {
+        let mut color = self.tint;
+        color.set_a(color.a() * self.opacity);
+        color
+    }
+
+    // Layers with the `y_sort` property are sorted against the characters.
+    // `y_sort_offset` moves the line they are sorted by up, in pixels.
+    pub fn y_sort_offset(&self) -> Option<f32> {
+        if !matches!(self.properties.get("y_sort"), Some(PropertyValue::BoolValue(true))) {
+            return None;
+        }
+
+        match self.properties.get("y_sort_offset") {
+            Some(PropertyValue::FloatValue(offset)) => Some(*offset),
+            Some(PropertyValue::IntValue(offset)) => Some(*offset as f32),
+            _ => Some(0.0),
+        }
+    }
+}
+
+fn multiply_tint(parent: Color, tint: Option<tiled::Color>) -> Color {
+    let Some(tint) = tint else {
+        return parent;
+    };
+
+    let tint = Color::rgba_u8(tint.red, tint.green, tint.blue, tint.alpha);
+    Color::rgba(
+        parent.r() * tint.r(),
+        parent.g() * tint.g(),
+        parent.b() * tint.b(),
+        parent.a() * tint.a(),
+    )
+}
+
+// Walks the layer hierarchy depth-first, so the resulting layers keep Tiled's draw order.
+// A layer's own properties override the ones of its parent groups.
+// Offsets add up, while opacity, tint and parallax factors multiply, as they do in Tiled.
+fn flatten_layers<'map>(
+    layers: impl Iterator<Item = tiled::Layer<'map>>,
+    parent: &FlattenedLayerParent,
+    flattened: &mut Vec<FlattenedLayer<'map>>,
+) {
+    for layer in layers {
+        let mut properties = parent.properties.clone();
+        properties.extend(layer.properties.clone());
+
+        let flattened_layer = FlattenedLayer {
+            map_offset: parent.map_offset,
+            offset_x: parent.offset_x + layer.offset_x,
+            offset_y: parent.offset_y + layer.offset_y,
+            visible: parent.visible && layer.visible,
+            opacity: parent.opacity * layer.opacity,
+            tint: multiply_tint(parent.tint, layer.tint_color),
+            parallax: parent.parallax * Vec2::new(layer.parallax_x, layer.parallax_y),
+            properties,
+            layer,
+        };
+
+        if let tiled::LayerType::GroupLayer(group) = flattened_layer.layer.layer_type() {
+            let group_parent = FlattenedLayerParent {
+                map_offset: flattened_layer.map_offset,
+                offset_x: flattened_layer.offset_x,
+                offset_y: flattened_layer.offset_y,
+                visible: flattened_layer.visible,
+                opacity: flattened_layer.opacity,
+                tint: flattened_layer.tint,
+                parallax: flattened_layer.parallax,
+                properties: flattened_layer.properties,
+            };
+            flatten_layers(group.layers(), &group_parent, flattened);
+            continue;
+        }
+
+        flattened.push(flattened_layer);
+    }
+}
+
+// What a group layer passes down to its children.
+struct FlattenedLayerParent {
+    map_offset: Vec2,
+    offset_x: f32,
+    offset_y: f32,
+    visible: bool,
+    opacity: f32,
+    tint: Color,
+    parallax: Vec2,
+    properties: Properties,
+}
+
+pub fn flattened_layers(map: &tiled::Map, map_offset: Vec2) -> Vec<FlattenedLayer> {
+    let root = FlattenedLayerParent {
+        map_offset,
+        offset_x: 0.0,
+        offset_y: 0.0,
+        visible: true,
+        opacity: 1.0,
+        tint: Color::WHITE,
+        parallax: Vec2::ONE,
+        properties: Properties::new(),
+    };
+
+    let mut flattened = Vec::new();
+    flatten_layers(map.layers(), &root, &mut flattened);
+    flattened
+}
+
+// The tilemaps are centered around the map's offset, while Tiled
+// positions layers relative to the top-left corner of the map.
+pub(crate) fn map_top_left(map: &tiled::Map) -> Vec2 {
+    Vec2::new(
+        -((map.width * map.tile_width) as f32) / 2.0,
+        (map.height * map.tile_height) as f32 / 2.0,
+    )
+}
+
+fn tiled_pos_to_world_pos(map_size: &TilemapSize, grid_size: &TilemapGridSize, map_type: &TilemapType, z: f32, offset_x: f32, offset_y: f32, tile_map_height: u32, world_pos: Vec2) -> Vec3 {
+    let tilemap_center_transform =
+        get_tilemap_center_transform(
+            map_size, grid_size, map_type, z,
+        ) * Transform::from_xyz(offset_x, -offset_y, 0.0);
+
+    // Get the tile pos as object position may not be what we need
+    let mut tile_pos = TilePos::from_world_pos(
+        &world_pos,
+        &map_size,
+        &grid_size,
+        &TilemapType::Square,
+    )
+    .unwrap_or(TilePos::default());
+
+    // We have different starting points for the tiles
+    // tiled - upper left
+    // bevy_ecs_tilemap - lower left
+    tile_pos.y = (tile_map_height - 1) - tile_pos.y;
+
+    let world_pos = tilemap_center_transform
+        * tile_pos.center_in_world(&grid_size, &map_type).extend(0.0);
+
+    world_pos
+}
+
+// Tiles aren't children of their tilemap, so they're despawned separately.
+pub(crate) fn despawn_with_tiles(commands: &mut Commands, tile_storage_q: &Query<&TileStorage>, entity: Entity) {
+    if let Ok(tile_storage) = tile_storage_q.get(entity) {
+        for tile in tile_storage.iter().flatten() {
+            commands.entity(*tile).despawn_recursive();
+        }
+    }
+
+    commands.entity(entity).despawn_recursive();
+}
+
+// Tilemaps, sprites and objects are children of their layer entity.
+pub(crate) fn despawn_map_layers(
+    commands: &mut Commands,
+    tile_storage_q: &Query<&TileStorage>,
+    children_q: &Query<&Children>,
+    layer_storage: &mut TiledLayersStorage,
+) {
+    for layer_entity in layer_storage.storage.values() {
+        if let Ok(children) = children_q.get(*layer_entity) {
+            for child in children.iter() {
+                despawn_with_tiles(commands, tile_storage_q, *child);
+            }
+        }
+
+        commands.entity(*layer_entity).despawn_recursive();
+    }
+    layer_storage.storage.clear();
+}
+
+// The entity the tilemaps, sprites and objects of a layer are spawned under.
+fn spawn_layer_entity(commands: &mut Commands, map: &tiled::Map, flattened_layer: &FlattenedLayer) -> Entity {
+    let layer = &flattened_layer.layer;
+    let layer_entity = commands
+        .spawn(SpatialBundle {
+            // Hidden layers are still spawned, so they can be shown at runtime.
+            visibility: Visibility {
+                is_visible: flattened_layer.visible,
+            },
+            ..Default::default()
+        })
+        .insert(Name::new(layer.name.clone()))
+        .id();
+
+    // The children are positioned relative to the layer entity.
+    if Parallax::is_needed(flattened_layer.parallax) {
+        commands.entity(layer_entity).insert(Parallax {
+            factor: flattened_layer.parallax,
+            origin: map_top_left(map) + flattened_layer.map_offset,
+            base: Vec3::ZERO,
+        });
+    }
+
+    if let Some(class) = &layer.user_type {
+        commands.add(InsertTiledClass {
+            entity: layer_entity,
+            class: class.clone(),
+            properties: layer.properties.clone(),
+        });
+    }
+
+    layer_entity
+}
+
+pub fn process_loaded_maps(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut map_events: EventReader<AssetEvent<TiledMap>>,
+    maps: Res<Assets<TiledMap>>,
+    tile_storage_query: Query<&TileStorage>,
+    children_query: Query<&Children>,
+    mut map_query: Query<(
+        Entity,
+        &Handle<TiledMap>,
+        &Transform,
+        &TileEdits,
+        &mut TiledLayersStorage,
+        &mut MapChunks,
+    )>,
+    new_maps: Query<&Handle<TiledMap>, Added<Handle<TiledMap>>>,
+    npc_query: Query<(Entity, &TiledObject), With<MapNpc>>,
+    mut tileset_props: ResMut<TilesProperties>,
+    // Grouped, as systems take at most 16 parameters.
+    (mut signs_res, mut npc_res, mut stashed_npcs): (
+        ResMut<SignsPool>,
+        ResMut<NpcPool>,
+        ResMut<StashedNpcs>,
+    ),
+    mut logic_res: ResMut<LogicLayers>,
+    class_registry: Res<TiledClassRegistry>,
+    chunk_streaming: Res<ChunkStreaming>,
+    mut rebuild_nav_grid: EventWriter<RebuildNavGrid>,
+) {
+    let mut changed_maps = Vec::<Handle<TiledMap>>::default();
+    for event in map_events.iter() {
+        match event {
+            AssetEvent::Created { handle } => {
+                log::info!("Map added!");
+                changed_maps.push(handle.clone());
+            }
+            AssetEvent::Modified { handle } => {
+                log::info!("Map changed!");
+                changed_maps.push(handle.clone());
+            }
+            AssetEvent::Removed { handle } => {
+                log::info!("Map removed!");
+                // if mesh was modified and removed in the same update, ignore the modification
+                // events are ordered so future modification events are ok
+                changed_maps.retain(|changed_handle| changed_handle == handle);
+            }
+        }
+    }
+
+    // If we have new map entities add them to the changed_maps list.
+    for new_map_handle in new_maps.iter() {
+        changed_maps.push(new_map_handle.clone_weak());
+    }
+
+    for changed_map in changed_maps.iter() {
+        for (map_entity, map_handle, map_transform, tile_edits, mut layer_storage, mut chunks) in
+            map_query.iter_mut()
+        {
+            // only deal with currently changed map
+            if map_handle != changed_map {
+                continue;
+            }
+
+            let tile_map_opt = maps.get(map_handle);
+            if tile_map_opt.is_none() {
+                continue;
+            }
+
+            let tiled_map = tile_map_opt.unwrap();
+
+            // TODO: Create a RemoveMap component..
+            despawn_map_layers(&mut commands, &tile_storage_query, &children_query, &mut layer_storage);
+            chunks.loaded.clear();
+
+            let map_offset = map_transform.translation.truncate();
+            let layers = flattened_layers(&tiled_map.map, map_offset);
+
+            // Process logic layers first. Their data is needed in order to insert
+            // the proper properties for the actual layers' tiles.
+            logic_res.maps.insert(map_entity, collect_logic_layers(&tiled_map.map, &layers));
+            tileset_props.props.insert(map_entity, collect_tile_properties(&tiled_map.map));
+
+            for (layer_index, flattened_layer) in layers.iter().enumerate() {
+                let layer = &flattened_layer.layer;
+                let is_logic_layer = layer.name.starts_with("Logic");
+                if is_logic_layer {
+                    continue;
+                }
+
+                match layer.layer_type() {
+                    tiled::LayerType::ImageLayer(img_layer) => {
+                        log::info!("Processing image layer: {}", layer.name);
+
+                        if let Some(layer_entity) = image_layer::spawn_image_layer(
+                            &mut commands,
+                            &asset_server,
+                            &tiled_map.map,
+                            flattened_layer,
+                            &img_layer,
+                        ) {
+                            layer_storage
+                                .storage
+                                .insert(layer_index as u32, layer_entity);
+                        }
+                        continue;
+                    }
+                    tiled::LayerType::ObjectLayer(obj_layer) => {
+                        if !flattened_layer.visible {
+                            continue;
+                        }
+
+                        log::info!(
+                            "Processing object layer: {}. Object count: {}",
+                            layer.name,
+                            obj_layer.objects().count()
+                        );
+                    }
+                    tiled::LayerType::TileLayer(tiled::TileLayer::Finite(_)) => {
+                        log::info!("Processing layer: {}", layer.name);
+                    }
+                    _ => {
+                        log::info!(
+                            "Skipping layer {} because only finite layers are supported.",
+                            layer.id()
+                        );
+                        continue;
+                    }
+                }
+
+                let layer_entity = spawn_layer_entity(&mut commands, &tiled_map.map, flattened_layer);
+                layer_storage
+                    .storage
+                    .insert(layer_index as u32, layer_entity);
+            }
+
+            // NPCs spawned from the map before it was reloaded are replaced by its new ones.
+            for (npc_entity, tiled_object) in npc_query.iter() {
+                if tiled_object.map == map_entity {
+                    commands.entity(npc_entity).despawn_recursive();
+                }
+            }
+
+            let PooledObjects { npcs, signs } =
+                collect_pooled_objects(&tiled_map.map, map_entity, map_offset, &class_registry);
+            signs_res.signs.insert(map_entity, signs);
+
+            // With streaming, the tiles, objects and NPCs are spawned
+            // by `stream_chunks` once the camera gets close to them.
+            if chunk_streaming.enabled {
+                npc_res.set_map_npcs(map_entity, Vec::new());
+                stashed_npcs.set_map_npcs(map_entity, npcs);
+            } else {
+                chunks::spawn_region(
+                    &mut commands,
+                    &asset_server,
+                    tiled_map,
+                    map_entity,
+                    map_offset,
+                    &layer_storage,
+                    tile_edits,
+                    &class_registry,
+                    TileRegion::whole(&tiled_map.map),
+                );
+                stashed_npcs.set_map_npcs(map_entity, Vec::new());
+                npc_res.set_map_npcs(map_entity, npcs);
+            }
+
+            rebuild_nav_grid.send(RebuildNavGrid { map: map_entity });
+        }
+    }
+}
+
+// Logic layers are the ones whose name starts with "Logic". They describe
+// the layer named by their `parent` property.
+fn collect_logic_layers(map: &tiled::Map, layers: &[FlattenedLayer]) -> MapLogicLayers {
+    let mut logic_layers = HashMap::new();
+    for flattened_layer in layers.iter() {
+        let layer = &flattened_layer.layer;
+        let is_logic_layer = layer.name.starts_with("Logic");
+        if !is_logic_layer {
+            continue;
+        }
+
+        let parent;
+        if let Some(PropertyValue::StringValue(p)) = flattened_layer.properties.get("parent") {
+            log::info!("Insert new logic layer: {}. Parent: {}", layer.name, p);
+            parent = p;
+        } else {
+            // No parent layer
+            continue;
+        }
+
+        let tiled::LayerType::TileLayer(tile_layer) = layer.layer_type() else {
+            log::info!(
+                "Skipping layer {} because only tile layers are supported.",
+                layer.id()
+            );
+            continue;
+        };
+        let tiled::TileLayer::Finite(layer_data) = tile_layer else {
+            log::info!(
+                "Skipping layer {} because only finite layers are supported.",
+                layer.id()
+            );
+            continue;
+        };
+
+        // Do something with each tile from this logic layer.
+        let mut v = Vec::new();
+        v.resize(map.width as usize * map.height as usize, None);
+        for x in 0..map.width {
+            for y in 0..map.height {
+                let mut mapped_y = y;
+                if map.orientation == tiled::Orientation::Orthogonal {
+                    mapped_y = (map.height - 1) - y;
+                }
+
+                let mapped_x = x as i32;
+                let mapped_y = mapped_y as i32;
+
+                let layer_tile = match layer_data.get_tile(mapped_x, mapped_y) {
+                    Some(t) => t,
+                    None => {
+                        continue;
+                    }
+                };
+
+                v[(mapped_y * map.width as i32 + mapped_x) as usize] = Some(layer_tile.id());
+            }
+        }
+
+        logic_layers.insert(parent.clone(), v);
+    }
+
+    MapLogicLayers {
+        width: map.width,
+        height: map.height,
+        layers: logic_layers,
+    }
+}
+
+// The properties of every tile, by tileset index and tile id.
+fn collect_tile_properties(map: &tiled::Map) -> Vec<Vec<Properties>> {
+    let mut props = vec![Vec::new(); map.tilesets().len()];
+    for (tileset_index, tileset) in map.tilesets().iter().enumerate() {
+        log::info!("Processing tileset: {}", tileset_index);
+
+        // Tile ids of image collections may have gaps.
+        let tile_cnt = tileset.tiles().map(|(tile_id, _)| tile_id as usize + 1).max().unwrap_or(0);
+        props[tileset_index].resize(tile_cnt, HashMap::new());
+        for (tile_id, tile) in tileset.tiles() {
+            props[tileset_index][tile_id as usize] = tile.properties.clone();
+        }
+    }
+
+    props
+}
+
+// The NPCs and signs of a map. They're kept in pools instead of being spawned with their layer.
+#[derive(Default, Debug)]
+pub(crate) struct PooledObjects {
+    pub npcs: Vec<NpcData>,
+    pub signs: Vec<SignData>,
+}
+
+// Collects the pooled objects of the visible object layers, placed in the world
+// like the layers of a map centered around `map_offset`.
+pub(crate) fn collect_pooled_objects(
+    map: &tiled::Map,
+    map_entity: Entity,
+    map_offset: Vec2,
+    class_registry: &TiledClassRegistry,
+) -> PooledObjects {
+    let map_size = TilemapSize {
+        x: map.width,
+        y: map.height,
+    };
+
+    let grid_size = TilemapGridSize {
+        x: map.tile_width as f32,
+        y: map.tile_height as f32,
+    };
+
+    let map_type = TilemapType::Square;
+
+    // Objects are accumulated over all object layers.
+    let mut pooled_objects = PooledObjects::default();
+
+    for flattened_layer in flattened_layers(map, map_offset).iter() {
+        let tiled::LayerType::ObjectLayer(obj_layer) = flattened_layer.layer.layer_type() else {
+            continue;
+        };
+        if !flattened_layer.visible {
+            continue;
+        }
+
+        let offset_x = flattened_layer.offset_x;
+        let offset_y = flattened_layer.offset_y;
+        let map_offset = map_offset.extend(0.0);
+
+        for object in obj_layer.objects() {
+            let pooled = class_registry.pooled(&object.user_type);
+            if pooled == Some(PooledObject::Npc) {
+                let id = match object.properties.get("id") {
+                    Some(tiled::PropertyValue::StringValue(id)) => id.clone(),
+                    _ => String::new(),
+                };
+
+                if id.is_empty() {
+                    log::warn!(
+                        "Skipping NPC object {} of layer {}: it has no `id` property.",
+                        object.id(),
+                        flattened_layer.layer.name
+                    );
+                    continue;
+                }
+
+                let z = match object.properties.get("z") {
+                    Some(tiled::PropertyValue::IntValue(z)) => *z,
+                    _ => -1,
+                };
+
+                if z < 0 {
+                    log::warn!(
+                        "Skipping NPC {id} (object {}) of layer {}: its `z` property is missing or negative.",
+                        object.id(),
+                        flattened_layer.layer.name
+                    );
+                    continue;
+                }
+
+                let world_pos = tiled_pos_to_world_pos(&map_size, &grid_size, &map_type, z as f32, offset_x, offset_y, map.height, Vec2::new(object.x, object.y)) + map_offset;
+
+                pooled_objects.npcs.push(NpcData {
+                    name: id,
+                    class: object.user_type.clone(),
+                    pos: world_pos,
+                    properties: object.properties.clone(),
+                    state: NpcState::default(),
+                    object: TiledObject {
+                        map: map_entity,
+                        id: object.id(),
+                        spawn_pos: world_pos,
+                    },
+                });
+                continue;
+            }
+
+            if pooled == Some(PooledObject::Sign) {
+                let id = match object.properties.get("id") {
+                    Some(tiled::PropertyValue::IntValue(id)) => *id,
+                    _ => 0,
+                };
+
+                let z = match object.properties.get("z") {
+                    Some(tiled::PropertyValue::IntValue(z)) => *z as f32,
+                    _ => 0f32,
+                };
+
+                let world_pos = tiled_pos_to_world_pos(&map_size, &grid_size, &map_type, z, offset_x, offset_y, map.height, Vec2::new(object.x, object.y)) + map_offset;
+
+                pooled_objects.signs.push(SignData {
+                    object_id: Some(object.id()),
+                    class: object.user_type.clone(),
+                    x: world_pos.x,
+                    y: world_pos.y,
+                    id: id as u32,
+                    properties: object.properties.clone(),
+                });
+            }
+        }
+    }
+
+    pooled_objects
+}
+
+// Drops what's kept for maps whose entity was despawned, e.g. when their world was reloaded.
+fn forget_despawned_maps(
+    mut commands: Commands,
+    despawned_maps: RemovedComponents<TiledMapBundleMarker>,
+    npc_query: Query<(Entity, &TiledObject), With<MapNpc>>,
+    mut tileset_props: ResMut<TilesProperties>,
+    mut logic_res: ResMut<LogicLayers>,
+    mut signs_res: ResMut<SignsPool>,
+    mut npc_res: ResMut<NpcPool>,
+    mut stashed_npcs: ResMut<StashedNpcs>,
+) {
+    for map_entity in despawned_maps.iter() {
+        tileset_props.props.remove(&map_entity);
+        logic_res.maps.remove(&map_entity);
+        signs_res.signs.remove(&map_entity);
+        npc_res.set_map_npcs(map_entity, Vec::new());
+        stashed_npcs.set_map_npcs(map_entity, Vec::new());
+
+        for (npc_entity, tiled_object) in npc_query.iter() {
+            if tiled_object.map == map_entity {
+                commands.entity(npc_entity).despawn_recursive();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn class_registry() -> TiledClassRegistry {
+        let mut class_registry = TiledClassRegistry::default();
+        class_registry.register_pooled(prototyp::map_classes::NPC, PooledObject::Npc);
+        class_registry.register_pooled(prototyp::map_classes::SIGN, PooledObject::Sign);
+        class_registry
+    }
+
+    // Collects the pooled objects of each map of a world, like `process_loaded_maps` does.
+    fn load_world_objects(
+        world_path: &str,
+        class_registry: &TiledClassRegistry,
+        signs_res: &mut SignsPool,
+        npc_res: &mut NpcPool,
+    ) -> Vec<Entity> {
+        let world_path = Path::new(world_path);
+        let world_file = world::parse_world(&std::fs::read(world_path).unwrap()).unwrap();
+
+        let mut map_entities = Vec::new();
+        for (index, world_map) in world_file.maps.iter().enumerate() {
+            let map_path = world_path.parent().unwrap().join(&world_map.file_name);
+            let map = prototyp::tmx::load_map(map_path).unwrap();
+            let map_entity = Entity::from_raw(index as u32);
+            let map_offset = world::map_translation(
+                Vec3::ZERO,
+                Vec2::new(world_map.x, world_map.y),
+                Vec2::new(world_map.width, world_map.height),
+            )
+            .truncate();
+
+            let PooledObjects { npcs, signs } = collect_pooled_objects(&map, map_entity, map_offset, class_registry);
+            signs_res.signs.insert(map_entity, signs);
+            npc_res.set_map_npcs(map_entity, npcs);
+            map_entities.push(map_entity);
+        }
+
+        map_entities
+    }
+
+    #[test]
+    fn simple_map_pools_its_npcs_and_signs() {
+        let map = prototyp::tmx::load_map("assets/map/simple.tmx").unwrap();
+        let PooledObjects { npcs, signs } =
+            collect_pooled_objects(&map, Entity::from_raw(0), Vec2::ZERO, &class_registry());
+
+        let mut names: Vec<&str> = npcs.iter().map(|npc| npc.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, ["patient", "talking_npc"]);
+        // The z of NPCs comes from their template.
+        assert!(npcs.iter().all(|npc| npc.pos.z == 10.0));
+        assert_eq!(signs.len(), 2);
+    }
+
+    #[test]
+    fn world_maps_keep_their_npcs_and_signs() {
+        let class_registry = class_registry();
+        let mut signs_res = SignsPool::default();
+        let mut npc_res = NpcPool::default();
+        let map_entities = load_world_objects("assets/map/simple.world", &class_registry, &mut signs_res, &mut npc_res);
+        let [simple, east] = map_entities[..] else {
+            panic!("Expected two maps, got {}", map_entities.len());
+        };
+
+        let npc_count = |npc_res: &NpcPool, map| npc_res.npcs.iter().filter(|npc| npc.object.map == map).count();
+        assert_eq!(signs_res.signs[&simple].len(), 2);
+        assert_eq!(signs_res.signs[&east].len(), 1);
+        assert_eq!(npc_count(&npc_res, simple), 2);
+        assert_eq!(npc_count(&npc_res, east), 1);
+
+        // The east map is placed right of the 960px wide simple map.
+        assert!(signs_res.signs[&east].iter().all(|sign| sign.x > 960.0));
+        assert!(npc_res
+            .npcs
+            .iter()
+            .filter(|npc| npc.object.map == east)
+            .all(|npc| npc.pos.x > 960.0));
+
+        // Reloading a map only replaces its own objects.
+        let map = prototyp::tmx::load_map("assets/map/simple.tmx").unwrap();
+        let PooledObjects { npcs, signs } =
+            collect_pooled_objects(&map, simple, Vec2::new(480.0, -480.0), &class_registry);
+        signs_res.signs.insert(simple, signs);
+        npc_res.set_map_npcs(simple, npcs);
+
+        assert_eq!(signs_res.signs[&east].len(), 1);
+        assert_eq!(npc_count(&npc_res, simple), 2);
+        assert_eq!(npc_count(&npc_res, east), 1);
+    }
+}
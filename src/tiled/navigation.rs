@@ -0,0 +1,404 @@
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use tiled::PropertyValue;
+
+use crate::resources::{LogicLayers, MapLogicLayers};
+
+use super::{
+    collision::{tile_collision_shapes, TileCollisionShape, TileOrientation},
+    edit::{self, TileEdits},
+    flattened_layers, map_top_left, TiledMap,
+};
+
+// Cost of a diagonal step relative to a straight one.
+const DIAGONAL_COST: f32 = std::f32::consts::SQRT_2;
+
+/// Walkable space of the loaded maps, with a cell per map tile.
+///
+/// The maps of a world share the grid, which covers all of them. Cells outside of
+/// any map are blocked, and maps with another tile size than the first one are left out.
+/// Cells are addressed from the bottom-left corner of the grid - for a single map,
+/// like the tiles in `TileQuery`. A cell is blocked when a tile or tile object collider covers
+/// its center or at least a quarter of it. If the map has a logic layer with the
+/// `parent` property set to `walkable`, only the cells with a tile on it are walkable.
+/// The `nav_cost` tile property makes cells more expensive to walk through.
+/// Tiles edited at runtime are taken into account.
+#[derive(Resource, Default, Debug)]
+pub struct NavGrid {
+    pub width: u32,
+    pub height: u32,
+    pub cell_size: Vec2,
+    // World position of the bottom-left corner of the grid.
+    pub origin: Vec2,
+    // Cost of entering each cell, None if it's blocked.
+    costs: Vec<Option<f32>>,
+}
+
+/// Rebuilds the `NavGrid` after a map entity changed. Sent when a map finishes loading.
+pub struct RebuildNavGrid {
+    pub map: Entity,
+}
+
+impl NavGrid {
+    pub fn new(width: u32, height: u32, cell_size: Vec2, origin: Vec2) -> Self {
+        Self {
+            width,
+            height,
+            cell_size,
+            origin,
+            costs: vec![Some(1.0); width as usize * height as usize],
+        }
+    }
+
+    fn index(&self, pos: TilePos) -> Option<usize> {
+        if pos.x >= self.width || pos.y >= self.height {
+            return None;
+        }
+
+        Some((pos.y * self.width + pos.x) as usize)
+    }
+
+    pub fn in_bounds(&self, pos: TilePos) -> bool {
+        self.index(pos).is_some()
+    }
+
+    /// Cost of entering the cell at `pos`. None if it's blocked or outside of the grid.
+    pub fn cost(&self, pos: TilePos) -> Option<f32> {
+        self.costs[self.index(pos)?]
+    }
+
+    pub fn set_cost(&mut self, pos: TilePos, cost: Option<f32>) {
+        if let Some(index) = self.index(pos) {
+            self.costs[index] = cost;
+        }
+    }
+
+    pub fn is_walkable(&self, pos: TilePos) -> bool {
+        self.cost(pos).is_some()
+    }
+
+    pub fn cell_at(&self, world_pos: Vec2) -> Option<TilePos> {
+        let cell = ((world_pos - self.origin) / self.cell_size).floor();
+        if cell.x < 0.0 || cell.y < 0.0 {
+            return None;
+        }
+
+        let pos = TilePos {
+            x: cell.x as u32,
+            y: cell.y as u32,
+        };
+        self.in_bounds(pos).then_some(pos)
+    }
+
+    pub fn cell_center(&self, pos: TilePos) -> Vec2 {
+        self.origin + (Vec2::new(pos.x as f32, pos.y as f32) + Vec2::splat(0.5)) * self.cell_size
+    }
+
+    /// The walkable cells reachable in a single step from `pos`, along with the cost of the step.
+    /// Diagonal steps aren't allowed to cut the corners of blocked cells.
+    pub fn neighbors(&self, pos: TilePos) -> Vec<(TilePos, f32)> {
+        let offset = |dx: i64, dy: i64| {
+            let x = u32::try_from(pos.x as i64 + dx).ok()?;
+            let y = u32::try_from(pos.y as i64 + dy).ok()?;
+            let neighbor = TilePos { x, y };
+            self.is_walkable(neighbor).then_some(neighbor)
+        };
+
+        let mut neighbors = Vec::with_capacity(8);
+        for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            if let Some(neighbor) = offset(dx, dy) {
+                neighbors.push((neighbor, self.cost(neighbor).unwrap_or_default()));
+            }
+        }
+
+        for (dx, dy) in [(1, 1), (1, -1), (-1, 1), (-1, -1)] {
+            if offset(dx, 0).is_none() || offset(0, dy).is_none() {
+                continue;
+            }
+
+            if let Some(neighbor) = offset(dx, dy) {
+                neighbors.push((neighbor, DIAGONAL_COST * self.cost(neighbor).unwrap_or_default()));
+            }
+        }
+
+        neighbors
+    }
+
+    // Blocks the cells covered by a collision shape centered at `center`.
+    fn block_shape(&mut self, center: Vec2, shape: TileCollisionShape) {
+        let half_extents = shape.half_extents();
+        let min = ((center - half_extents - self.origin) / self.cell_size)
+            .floor()
+            .max(Vec2::ZERO);
+        let max = ((center + half_extents - self.origin) / self.cell_size)
+            .floor()
+            .min(Vec2::new(self.width as f32, self.height as f32) - Vec2::ONE);
+        if max.x < min.x || max.y < min.y {
+            return;
+        }
+
+        let cell_area = self.cell_size.x * self.cell_size.y;
+        for x in min.x as u32..=max.x as u32 {
+            for y in min.y as u32..=max.y as u32 {
+                let pos = TilePos { x, y };
+                let cell_center = self.cell_center(pos);
+
+                let overlap = (self.cell_size / 2.0 + half_extents - (cell_center - center).abs())
+                    .max(Vec2::ZERO)
+                    .min(self.cell_size);
+                if shape.contains(cell_center - center) || overlap.x * overlap.y >= cell_area / 4.0 {
+                    self.set_cost(pos, None);
+                }
+            }
+        }
+    }
+
+    // A grid covering all `grids`, with the cells between them blocked.
+    fn merge(grids: &[NavGrid]) -> NavGrid {
+        let Some(first) = grids.first() else {
+            return NavGrid::default();
+        };
+        let cell_size = first.cell_size;
+        let grids: Vec<&NavGrid> = grids
+            .iter()
+            .filter(|grid| {
+                let same_size = grid.cell_size == cell_size;
+                if !same_size {
+                    log::warn!(
+                        "Leaving a map out of the navigation grid, its tiles are {} instead of {}",
+                        grid.cell_size,
+                        cell_size
+                    );
+                }
+                same_size
+            })
+            .collect();
+
+        let min = grids.iter().fold(first.origin, |min, grid| min.min(grid.origin));
+        let max = grids.iter().fold(first.origin, |max, grid| max.max(grid.origin + grid.size()));
+        let cells = ((max - min) / cell_size).round();
+
+        let mut merged = NavGrid::new(cells.x as u32, cells.y as u32, cell_size, min);
+        merged.costs.fill(None);
+        for grid in grids {
+            let offset = ((grid.origin - min) / cell_size).round();
+            for x in 0..grid.width {
+                for y in 0..grid.height {
+                    let pos = TilePos {
+                        x: offset.x as u32 + x,
+                        y: offset.y as u32 + y,
+                    };
+                    merged.set_cost(pos, grid.cost(TilePos { x, y }));
+                }
+            }
+        }
+
+        merged
+    }
+
+    fn size(&self) -> Vec2 {
+        Vec2::new(self.width as f32, self.height as f32) * self.cell_size
+    }
+
+    // Cells get the highest `nav_cost` of the tiles on them.
+    fn raise_cost(&mut self, pos: TilePos, cost: f32) {
+        if let Some(current) = self.cost(pos) {
+            self.set_cost(pos, Some(current.max(cost)));
+        }
+    }
+}
+
+fn nav_cost(tile: &tiled::Tile) -> Option<f32> {
+    match tile.properties.get("nav_cost") {
+        Some(PropertyValue::FloatValue(cost)) => Some(*cost),
+        Some(PropertyValue::IntValue(cost)) => Some(*cost as f32),
+        _ => None,
+    }
+}
+
+pub fn build_nav_grid(
+    tiled_map: &TiledMap,
+    map_offset: Vec2,
+    tile_edits: &TileEdits,
+    logic_layers: Option<&MapLogicLayers>,
+) -> NavGrid {
+    let map = &tiled_map.map;
+    let grid_size = TilemapGridSize {
+        x: map.tile_width as f32,
+        y: map.tile_height as f32,
+    };
+    let map_px = Vec2::new(
+        (map.width * map.tile_width) as f32,
+        (map.height * map.tile_height) as f32,
+    );
+    let origin = map_top_left(map) + map_offset - Vec2::new(0.0, map_px.y);
+
+    let mut grid = NavGrid::new(map.width, map.height, Vec2::new(grid_size.x, grid_size.y), origin);
+
+    for flattened_layer in flattened_layers(map, map_offset).iter() {
+        if flattened_layer.layer.name.starts_with("Logic") {
+            continue;
+        }
+
+        match flattened_layer.layer.layer_type() {
+            tiled::LayerType::TileLayer(tiled::TileLayer::Finite(layer_data)) => {
+                let tilemap_transform = flattened_layer.tilemap_transform(map, 0.0);
+                let layer_edits = tile_edits.layer(&flattened_layer.layer.name);
+
+                for x in 0..map.width {
+                    for y in 0..map.height {
+                        let Some(map_tile) = edit::layer_tile(map, &layer_data, layer_edits, UVec2::new(x, y)) else {
+                            continue;
+                        };
+                        let Some(tile) = map_tile.tile(map) else {
+                            continue;
+                        };
+
+                        let pos = TilePos { x, y };
+                        if let Some(cost) = nav_cost(&tile) {
+                            grid.raise_cost(pos, cost);
+                        }
+
+                        let orientation = map_tile.orientation();
+                        let cell_center = (tilemap_transform
+                            * pos.center_in_world(&grid_size, &TilemapType::Square).extend(0.0))
+                        .truncate();
+
+                        // Tiles of image collections spawned as sprites are anchored at the
+                        // bottom-left corner of their cell, the others at its center.
+                        let tileset = &map.tilesets()[map_tile.tileset_index];
+                        let (tile_center, tile_size) = match &tile.image {
+                            Some(image)
+                                if !tiled_map.tilemap_textures.contains_key(&map_tile.tileset_index) =>
+                            {
+                                let image_size = Vec2::new(image.width as f32, image.height as f32);
+                                let tileset_offset =
+                                    Vec2::new(tileset.offset_x as f32, -tileset.offset_y as f32);
+                                let cell_bottom_left = cell_center - grid.cell_size / 2.0;
+                                (cell_bottom_left + tileset_offset + image_size / 2.0, image_size)
+                            }
+                            _ => (
+                                cell_center,
+                                Vec2::new(tileset.tile_width as f32, tileset.tile_height as f32),
+                            ),
+                        };
+
+                        for (offset, shape) in tile_collision_shapes(&tile, tile_size, orientation) {
+                            grid.block_shape(tile_center + offset, shape);
+                        }
+                    }
+                }
+            }
+            tiled::LayerType::ObjectLayer(obj_layer) => {
+                for object in obj_layer.objects() {
+                    let (Some(tile), &tiled::ObjectShape::Rect { width, height }) =
+                        (object.get_tile().and_then(|t| t.get_tile()), &object.shape)
+                    else {
+                        continue;
+                    };
+                    let Some(image) = &tile.image else {
+                        continue;
+                    };
+
+                    // Tile objects are stretched to their size and rotated around their
+                    // bottom-left corner. Rotated shapes are approximated by their bounds.
+                    let size = Vec2::new(width, height);
+                    let image_size = Vec2::new(image.width as f32, image.height as f32);
+                    let scale = size / image_size;
+                    let angle = -object.rotation.to_radians();
+                    let rotation = Mat2::from_angle(angle);
+                    let (sin, cos) = (angle.sin().abs(), angle.cos().abs());
+                    let bottom_left = map_top_left(map)
+                        + map_offset
+                        + Vec2::new(
+                            flattened_layer.offset_x + object.x,
+                            -(flattened_layer.offset_y + object.y),
+                        );
+
+                    for (offset, shape) in tile_collision_shapes(&tile, image_size, TileOrientation::default()) {
+                        let center = bottom_left + rotation * (size / 2.0 + offset * scale);
+                        let half_extents = shape.half_extents() * scale;
+                        let bounds = Vec2::new(
+                            cos * half_extents.x + sin * half_extents.y,
+                            sin * half_extents.x + cos * half_extents.y,
+                        );
+                        grid.block_shape(center, TileCollisionShape::Rect { half_extents: bounds });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(logic_layers) = logic_layers.filter(|logic_layers| logic_layers.layers.contains_key("walkable")) {
+        for x in 0..map.width.min(logic_layers.width) {
+            for y in 0..map.height.min(logic_layers.height) {
+                if logic_layers.value("walkable", x, y).is_none() {
+                    grid.set_cost(TilePos { x, y }, None);
+                }
+            }
+        }
+    }
+
+    grid
+}
+
+pub fn rebuild_nav_grid(
+    mut events: EventReader<RebuildNavGrid>,
+    maps: Res<Assets<TiledMap>>,
+    map_q: Query<(Entity, &Handle<TiledMap>, &Transform, &TileEdits)>,
+    logic_layers: Res<LogicLayers>,
+    mut nav_grid: ResMut<NavGrid>,
+) {
+    if events.iter().count() == 0 {
+        return;
+    }
+
+    // The other maps are built again too, as they share the grid.
+    let grids: Vec<NavGrid> = map_q
+        .iter()
+        .filter_map(|(map_entity, map_handle, map_transform, tile_edits)| {
+            let tiled_map = maps.get(map_handle)?;
+            Some(build_nav_grid(
+                tiled_map,
+                map_transform.translation.truncate(),
+                tile_edits,
+                logic_layers.maps.get(&map_entity),
+            ))
+        })
+        .collect();
+
+    *nav_grid = NavGrid::merge(&grids);
+    log::info!(
+        "Built navigation grid of {}x{} cells from {} maps",
+        nav_grid.width,
+        nav_grid.height,
+        grids.len()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merged_grid_covers_all_maps() {
+        let cell_size = Vec2::splat(32.0);
+        let mut left = NavGrid::new(2, 2, cell_size, Vec2::new(-64.0, 0.0));
+        left.set_cost(TilePos { x: 0, y: 1 }, None);
+        // Placed right of the left map and one cell higher.
+        let mut right = NavGrid::new(3, 1, cell_size, Vec2::new(0.0, 32.0));
+        right.set_cost(TilePos { x: 2, y: 0 }, Some(4.0));
+
+        let merged = NavGrid::merge(&[left, right]);
+        assert_eq!((merged.width, merged.height), (5, 2));
+        assert_eq!(merged.origin, Vec2::new(-64.0, 0.0));
+
+        assert_eq!(merged.cost(TilePos { x: 0, y: 0 }), Some(1.0));
+        assert_eq!(merged.cost(TilePos { x: 0, y: 1 }), None);
+        assert_eq!(merged.cost(TilePos { x: 4, y: 1 }), Some(4.0));
+        // Below the right map, outside of both.
+        assert_eq!(merged.cost(TilePos { x: 2, y: 0 }), None);
+        assert_eq!(merged.cell_at(Vec2::new(80.0, 40.0)), Some(TilePos { x: 4, y: 1 }));
+    }
+}
@@ -0,0 +1,105 @@
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+
+use super::{
+    chunks::TileRegion, sprite_tiles, tiled_pos_to_world_pos, FlattenedLayer, InsertTiledClass,
+    TiledClassRegistry, TiledObject,
+};
+
+// The tile an object is placed on, in bevy_ecs_tilemap's coordinates.
+// Objects outside of the map belong to the closest tile on its edge.
+fn object_tile_pos(map: &tiled::Map, flattened_layer: &FlattenedLayer, object: &tiled::Object) -> UVec2 {
+    let x = ((flattened_layer.offset_x + object.x) / map.tile_width as f32)
+        .floor()
+        .clamp(0.0, (map.width - 1) as f32) as u32;
+    let row = ((flattened_layer.offset_y + object.y) / map.tile_height as f32)
+        .floor()
+        .clamp(0.0, (map.height - 1) as f32) as u32;
+
+    UVec2::new(x, (map.height - 1) - row)
+}
+
+// Spawns the objects of an object layer placed within `region`. NPCs and signs are
+// pooled by `process_loaded_maps` instead. Tile objects are spawned as sprites, any other
+// object only if its class maps to a component.
+pub(crate) fn spawn_objects(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    map: &tiled::Map,
+    map_entity: Entity,
+    flattened_layer: &FlattenedLayer,
+    obj_layer: &tiled::ObjectLayer,
+    class_registry: &TiledClassRegistry,
+    region: TileRegion,
+) -> Vec<Entity> {
+    let map_size = TilemapSize {
+        x: map.width,
+        y: map.height,
+    };
+
+    let grid_size = TilemapGridSize {
+        x: map.tile_width as f32,
+        y: map.tile_height as f32,
+    };
+
+    let map_type = TilemapType::Square;
+
+    let mut object_entities = Vec::new();
+    for object in obj_layer.objects() {
+        if class_registry.pooled(&object.user_type).is_some() {
+            continue;
+        }
+
+        if !region.contains(object_tile_pos(map, flattened_layer, &object)) {
+            continue;
+        }
+
+        // Tile objects, f.e furniture from image collections, are spawned as sprites.
+        let tile_object =
+            sprite_tiles::spawn_tile_object(commands, asset_server, map, flattened_layer, &object);
+
+        let spawned = if class_registry.contains(&object.user_type) {
+            let (object_entity, spawn_pos) = tile_object.unwrap_or_else(|| {
+                let z = match object.properties.get("z") {
+                    Some(tiled::PropertyValue::IntValue(z)) => *z as f32,
+                    _ => 0f32,
+                };
+
+                let world_pos = tiled_pos_to_world_pos(
+                    &map_size,
+                    &grid_size,
+                    &map_type,
+                    z,
+                    flattened_layer.offset_x,
+                    flattened_layer.offset_y,
+                    map.height,
+                    Vec2::new(object.x, object.y),
+                ) + flattened_layer.map_offset.extend(0.0);
+
+                let object_entity = commands
+                    .spawn(SpatialBundle::from_transform(Transform::from_translation(world_pos)))
+                    .id();
+                (object_entity, world_pos)
+            });
+            commands.add(InsertTiledClass {
+                entity: object_entity,
+                class: object.user_type.clone(),
+                properties: object.properties.clone(),
+            });
+            Some((object_entity, spawn_pos))
+        } else {
+            tile_object
+        };
+
+        if let Some((object_entity, spawn_pos)) = spawned {
+            commands.entity(object_entity).insert(TiledObject {
+                map: map_entity,
+                id: object.id(),
+                spawn_pos,
+            });
+            object_entities.push(object_entity);
+        }
+    }
+
+    object_entities
+}
@@ -0,0 +1,35 @@
+use bevy::prelude::*;
+
+use crate::components::MainCamera;
+
+// Moves a layer relative to the camera as Tiled's parallax factors do.
+// A factor of 1.0 moves with the map, 0.0 sticks to the camera.
+#[derive(Component, Debug)]
+pub struct Parallax {
+    pub factor: Vec2,
+    // World position of the map's parallax origin.
+    pub origin: Vec2,
+    // Layer translation when the camera is at the origin.
+    pub base: Vec3,
+}
+
+impl Parallax {
+    pub fn is_needed(factor: Vec2) -> bool {
+        factor != Vec2::ONE
+    }
+}
+
+pub fn apply_parallax(
+    camera_q: Query<&Transform, With<MainCamera>>,
+    mut parallax_q: Query<(&Parallax, &mut Transform), Without<MainCamera>>,
+) {
+    let Ok(camera_transform) = camera_q.get_single() else {
+        return;
+    };
+
+    for (parallax, mut transform) in parallax_q.iter_mut() {
+        let shift = (camera_transform.translation.truncate() - parallax.origin)
+            * (Vec2::ONE - parallax.factor);
+        transform.translation = parallax.base + shift.extend(0.0);
+    }
+}
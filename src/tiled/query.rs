@@ -0,0 +1,167 @@
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::{Entity, GlobalTransform, Query, Res, Vec2},
+};
+use bevy_ecs_tilemap::prelude::*;
+use tiled::Properties;
+
+use crate::resources::{LogicLayers, TilesProperties};
+
+use super::{TiledLayer, TiledTileId};
+
+#[derive(Debug)]
+pub struct TileInfo<'a> {
+    pub map: Entity,
+    pub layer: &'a str,
+    pub entity: Entity,
+    pub pos: TilePos,
+    pub tile_id: u32,
+    pub properties: Option<&'a Properties>,
+}
+
+/// Answers questions about the tiles of the loaded maps.
+///
+/// Positions are in bevy_ecs_tilemap's convention - `TilePos { x: 0, y: 0 }` is
+/// the bottom-left tile, while Tiled counts rows from the top. They're relative to
+/// the whole map, even when it's streamed in chunks. Lookups by position alone
+/// return the first map with a layer of that name covering it, the ones by world
+/// position find the map placed there.
+#[derive(SystemParam)]
+pub struct TileQuery<'w, 's> {
+    layers_q: Query<
+        'w,
+        's,
+        (
+            &'static TiledLayer,
+            &'static TileStorage,
+            &'static TilemapSize,
+            &'static TilemapGridSize,
+            &'static TilemapType,
+            &'static GlobalTransform,
+        ),
+    >,
+    tiles_q: Query<'w, 's, &'static TiledTileId>,
+    tileset_props: Res<'w, TilesProperties>,
+    logic_layers: Res<'w, LogicLayers>,
+}
+
+impl<'w, 's> TileQuery<'w, 's> {
+    /// Returns the position of the tile at `world_pos` on `layer`.
+    pub fn tile_pos(&self, layer: &str, world_pos: Vec2) -> Option<TilePos> {
+        self.map_tile_pos(layer, world_pos).map(|(_, pos)| pos)
+    }
+
+    /// Returns the map placed at `world_pos` and the position of its tile on `layer`.
+    pub fn map_tile_pos(&self, layer: &str, world_pos: Vec2) -> Option<(Entity, TilePos)> {
+        self.layers_q
+            .iter()
+            .filter(|(tiled_layer, ..)| tiled_layer.name == layer)
+            .find_map(|(tiled_layer, _, map_size, grid_size, map_type, transform)| {
+                let pos = world_to_tile_pos(world_pos, map_size, grid_size, map_type, transform)?;
+                Some((
+                    tiled_layer.map,
+                    TilePos {
+                        x: tiled_layer.origin.x + pos.x,
+                        y: tiled_layer.origin.y + pos.y,
+                    },
+                ))
+            })
+    }
+
+    /// Returns the tile on `layer` at `pos`.
+    pub fn tile(&self, layer: &str, pos: TilePos) -> Option<TileInfo> {
+        // A layer with tiles from several tilesets is split into several tilemaps.
+        self.layers_q
+            .iter()
+            .filter(|(tiled_layer, ..)| tiled_layer.name == layer)
+            .find_map(|(tiled_layer, storage, ..)| {
+                let local_pos = TilePos {
+                    x: pos.x.checked_sub(tiled_layer.origin.x)?,
+                    y: pos.y.checked_sub(tiled_layer.origin.y)?,
+                };
+                self.tile_info(tiled_layer, storage, local_pos)
+            })
+    }
+
+    /// Returns the tile on `layer` at `world_pos`.
+    pub fn tile_at(&self, layer: &str, world_pos: Vec2) -> Option<TileInfo> {
+        self.layers_q
+            .iter()
+            .filter(|(tiled_layer, ..)| tiled_layer.name == layer)
+            .find_map(|(tiled_layer, storage, map_size, grid_size, map_type, transform)| {
+                let pos = world_to_tile_pos(world_pos, map_size, grid_size, map_type, transform)?;
+                self.tile_info(tiled_layer, storage, pos)
+            })
+    }
+
+    /// Returns the tiles of all layers at `world_pos`.
+    pub fn tiles_at(&self, world_pos: Vec2) -> Vec<TileInfo> {
+        self.layers_q
+            .iter()
+            .filter_map(|(tiled_layer, storage, map_size, grid_size, map_type, transform)| {
+                let pos = world_to_tile_pos(world_pos, map_size, grid_size, map_type, transform)?;
+                self.tile_info(tiled_layer, storage, pos)
+            })
+            .collect()
+    }
+
+    /// Returns the value of the logic layer of `map` describing `layer` at `pos`.
+    pub fn logic_value(&self, map: Entity, layer: &str, pos: TilePos) -> Option<u32> {
+        self.logic_layers.maps.get(&map)?.value(layer, pos.x, pos.y)
+    }
+
+    /// Returns the value of the logic layer describing `layer` at `world_pos`.
+    pub fn logic_value_at(&self, layer: &str, world_pos: Vec2) -> Option<u32> {
+        let (map, pos) = self.map_tile_pos(layer, world_pos)?;
+        self.logic_value(map, layer, pos)
+    }
+
+    // `local_pos` is relative to the tilemap of `tiled_layer`.
+    fn tile_info<'a>(
+        &'a self,
+        tiled_layer: &'a TiledLayer,
+        storage: &TileStorage,
+        local_pos: TilePos,
+    ) -> Option<TileInfo<'a>> {
+        if !local_pos.within_map_bounds(&storage.size) {
+            return None;
+        }
+
+        let entity = storage.get(&local_pos)?;
+        let tile_id = self.tiles_q.get(entity).ok()?.0;
+        let properties = self
+            .tileset_props
+            .props
+            .get(&tiled_layer.map)
+            .and_then(|tilesets| tilesets.get(tiled_layer.tileset_index))
+            .and_then(|tiles| tiles.get(tile_id as usize));
+
+        Some(TileInfo {
+            map: tiled_layer.map,
+            layer: &tiled_layer.name,
+            entity,
+            pos: TilePos {
+                x: tiled_layer.origin.x + local_pos.x,
+                y: tiled_layer.origin.y + local_pos.y,
+            },
+            tile_id,
+            properties,
+        })
+    }
+}
+
+fn world_to_tile_pos(
+    world_pos: Vec2,
+    map_size: &TilemapSize,
+    grid_size: &TilemapGridSize,
+    map_type: &TilemapType,
+    map_transform: &GlobalTransform,
+) -> Option<TilePos> {
+    let local_pos = map_transform
+        .compute_matrix()
+        .inverse()
+        .transform_point3(world_pos.extend(0.0))
+        .truncate();
+
+    TilePos::from_world_pos(&local_pos, map_size, grid_size, map_type)
+}
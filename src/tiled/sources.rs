@@ -0,0 +1,160 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use prototyp::tmx::tags;
+
+/// The files a TMX map refers to, which the tiled crate resolves without keeping track of:
+/// external tilesets and object templates. Kept so exported maps refer to them too.
+///
+/// Paths are relative to the working directory, like the ones resolved by the tiled crate.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct MapSources {
+    // By tileset index, None for the tilesets embedded into the map.
+    pub tilesets: Vec<Option<PathBuf>>,
+    // The objects created from a template, by object id.
+    pub templates: HashMap<u32, TemplateInstance>,
+}
+
+/// An object created from a template. Only the attributes and properties set on the
+/// object itself are written to the map, the others come from the template.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct TemplateInstance {
+    pub template: PathBuf,
+    pub attributes: HashSet<String>,
+    pub properties: HashSet<String>,
+}
+
+impl MapSources {
+    /// Reads the sources of the TMX map `tmx`, loaded from `map_path`.
+    pub fn parse(tmx: &str, map_path: &Path) -> Self {
+        // References are relative to the map.
+        let dir = map_path.parent().unwrap_or_else(|| Path::new(""));
+        let mut sources = MapSources::default();
+
+        // Tilesets and objects nest other elements, such as the objects of tile collisions
+        // within an embedded tileset or the properties of class properties. Only the
+        // tilesets of the map and the properties of its objects are of interest.
+        let mut depth: usize = 0;
+        let mut tileset_depth = None;
+        let mut object: Option<(u32, usize)> = None;
+        for tag in tags(tmx) {
+            if tag.end {
+                depth = depth.saturating_sub(1);
+                if tileset_depth == Some(depth) {
+                    tileset_depth = None;
+                }
+                if object.map_or(false, |(_, object_depth)| object_depth == depth) {
+                    object = None;
+                }
+                continue;
+            }
+
+            match tag.name {
+                "tileset" if depth == 1 => {
+                    sources
+                        .tilesets
+                        .push(tag.attribute("source").map(|source| dir.join(source)));
+                    if !tag.empty {
+                        tileset_depth = Some(depth);
+                    }
+                }
+                "object" if object.is_none() && tileset_depth.is_none() => {
+                    let id = tag.attribute("id").and_then(|id| id.parse().ok());
+                    if let (Some(id), Some(template)) = (id, tag.attribute("template")) {
+                        sources.templates.insert(
+                            id,
+                            TemplateInstance {
+                                template: dir.join(template),
+                                attributes: tag
+                                    .attributes
+                                    .iter()
+                                    .map(|(name, _)| name.to_string())
+                                    .collect(),
+                                properties: HashSet::new(),
+                            },
+                        );
+                    }
+                    if let (Some(id), false) = (id, tag.empty) {
+                        object = Some((id, depth));
+                    }
+                }
+                // Directly in the object's <properties>.
+                "property" => {
+                    if let Some((id, _)) =
+                        object.filter(|(_, object_depth)| depth == object_depth + 2)
+                    {
+                        if let (Some(instance), Some(name)) =
+                            (sources.templates.get_mut(&id), tag.attribute("name"))
+                        {
+                            instance.properties.insert(name.to_string());
+                        }
+                    }
+                }
+                _ => {}
+            }
+
+            if !tag.empty {
+                depth += 1;
+            }
+        }
+
+        sources
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simple_map_sources() {
+        let map_path = Path::new("assets/map/simple.tmx");
+        let sources = MapSources::parse(&std::fs::read_to_string(map_path).unwrap(), map_path);
+
+        assert_eq!(
+            sources.tilesets,
+            ["bg.tsx", "plant.tsx", "numbers.tsx"]
+                .map(|tileset| Some(Path::new("assets/map").join(tileset)))
+        );
+
+        let npc = &sources.templates[&79];
+        assert_eq!(npc.template, Path::new("assets/map/templates/npc.tx"));
+        assert_eq!(npc.properties, HashSet::from(["id".to_string()]));
+        assert!(!npc.attributes.contains("width"));
+
+        let sign = &sources.templates[&1];
+        assert_eq!(sign.template, Path::new("assets/map/templates/sign.tx"));
+        assert!(sign.attributes.contains("width"));
+    }
+
+    #[test]
+    fn nested_elements_are_skipped() {
+        let tmx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map>
+ <!-- <tileset source="commented.tsx"/> -->
+ <tileset firstgid="1" name="embedded">
+  <tile id="0"><objectgroup><object id="1" template="no.tx" x="0" y="0"/></objectgroup></tile>
+ </tileset>
+ <objectgroup id="2" name="objects">
+  <object id="3" template="a &amp; b.tx" name="a > b" x="1" y="2">
+   <properties>
+    <property name="own" value="1"/>
+    <property name="nested" type="class"><properties><property name="inner" value="2"/></properties></property>
+   </properties>
+  </object>
+ </objectgroup>
+</map>"#;
+        let sources = MapSources::parse(tmx, Path::new("maps/map.tmx"));
+
+        assert_eq!(sources.tilesets, [None]);
+        assert_eq!(sources.templates.len(), 1);
+        let instance = &sources.templates[&3];
+        assert_eq!(instance.template, Path::new("maps/a & b.tx"));
+        assert_eq!(
+            instance.properties,
+            HashSet::from(["own".to_string(), "nested".to_string()])
+        );
+    }
+}
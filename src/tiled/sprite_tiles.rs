@@ -0,0 +1,184 @@
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use bevy_rapier2d::prelude::RigidBody;
+
+use crate::systems::ysort;
+
+use super::{
+    asset_path,
+    chunks::TileRegion,
+    collision::{self, TileOrientation},
+    edit::{self, LayerEdits},
+    map_top_left, FlattenedLayer, TiledSpriteTile, TiledTileId,
+};
+
+// Spawns the tiles of `tileset` within `region` of a layer as individual sprites. Used for
+// image collections whose images differ in size, which a tilemap can't render.
+// Like Tiled, images are anchored at the bottom-left corner of their cell.
+pub(crate) fn spawn_sprite_tiles(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    map: &tiled::Map,
+    flattened_layer: &FlattenedLayer,
+    layer_data: &tiled::FiniteTileLayer,
+    layer_edits: Option<&LayerEdits>,
+    tileset_index: usize,
+    tileset: &tiled::Tileset,
+    z: f32,
+    region: TileRegion,
+) -> Vec<Entity> {
+    let grid_size = TilemapGridSize {
+        x: map.tile_width as f32,
+        y: map.tile_height as f32,
+    };
+
+    let map_type = TilemapType::Square;
+
+    let tilemap_center_transform = flattened_layer.tilemap_transform(map, 0.0);
+
+    let tileset_offset = Vec2::new(tileset.offset_x as f32, -tileset.offset_y as f32);
+    let color = flattened_layer.color();
+    let y_sort_offset = flattened_layer.y_sort_offset();
+
+    let mut sprites = Vec::new();
+    for UVec2 { x, y } in region.positions() {
+        let Some(map_tile) = edit::layer_tile(map, layer_data, layer_edits, UVec2::new(x, y)) else {
+            continue;
+        };
+
+        if map_tile.tileset_index != tileset_index {
+            continue;
+        }
+
+        let Some(tile) = map_tile.tile(map) else {
+            continue;
+        };
+
+        let Some(image) = &tile.image else {
+            continue;
+        };
+
+        let image_size = Vec2::new(image.width as f32, image.height as f32);
+        let cell_bottom_left = (tilemap_center_transform
+            * TilePos { x, y }.center_in_world(&grid_size, &map_type).extend(0.0))
+        .truncate()
+            - Vec2::new(grid_size.x, grid_size.y) / 2.0;
+        let image_center = cell_bottom_left + tileset_offset + image_size / 2.0;
+
+        let sprite_z = match y_sort_offset {
+            Some(offset) => ysort::sorted_z(cell_bottom_left.y + offset),
+            None => z,
+        };
+
+        let (flip_x, flip_y, rotation) = map_tile.orientation().sprite_flips();
+
+        let sprite_entity = commands
+            .spawn(SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    flip_x,
+                    flip_y,
+                    ..default()
+                },
+                texture: asset_server.load(asset_path(&image.source)),
+                transform: Transform::from_translation(image_center.extend(sprite_z))
+                    .with_rotation(rotation),
+                ..default()
+            })
+            .insert(TiledTileId(map_tile.tile_id))
+            .insert(TiledSpriteTile(TilePos { x, y }))
+            .id();
+
+        // The colliders are children of the rotated sprite, so only
+        // the mirroring needs to be applied to them.
+        let collider_orientation = TileOrientation {
+            flip_h: flip_x,
+            flip_v: flip_y,
+            flip_d: false,
+        };
+
+        if collision::spawn_tile_colliders(commands, sprite_entity, &tile, image_size, collider_orientation) {
+            commands.entity(sprite_entity).insert(RigidBody::Fixed);
+        }
+
+        sprites.push(sprite_entity);
+    }
+
+    sprites
+}
+
+// Tile objects are anchored at their bottom-left corner and rotated around it.
+// Their image is stretched to the size of the object. Returns the entity and its translation.
+pub(crate) fn spawn_tile_object(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    map: &tiled::Map,
+    flattened_layer: &FlattenedLayer,
+    object: &tiled::Object,
+) -> Option<(Entity, Vec3)> {
+    let tile = object.get_tile()?.get_tile()?;
+    let image = tile.image.as_ref()?;
+    let &tiled::ObjectShape::Rect { width, height } = &object.shape else {
+        return None;
+    };
+
+    let size = Vec2::new(width, height);
+    let image_size = Vec2::new(image.width as f32, image.height as f32);
+    let bottom_left = map_top_left(map)
+        + flattened_layer.map_offset
+        + Vec2::new(
+            flattened_layer.offset_x + object.x,
+            -(flattened_layer.offset_y + object.y),
+        );
+
+    let z = match (object.properties.get("z"), flattened_layer.y_sort_offset()) {
+        (Some(tiled::PropertyValue::IntValue(z)), _) => *z as f32,
+        (_, Some(offset)) => ysort::sorted_z(bottom_left.y + offset),
+        _ => match flattened_layer.properties.get("z") {
+            Some(tiled::PropertyValue::IntValue(z)) => *z as f32,
+            _ => 0.0,
+        },
+    };
+
+    let object_entity = commands
+        .spawn(SpriteBundle {
+            sprite: Sprite {
+                color: flattened_layer.color(),
+                custom_size: Some(size),
+                anchor: bevy::sprite::Anchor::BottomLeft,
+                ..default()
+            },
+            texture: asset_server.load(asset_path(&image.source)),
+            transform: Transform::from_translation(bottom_left.extend(z))
+                .with_rotation(Quat::from_rotation_z(-object.rotation.to_radians())),
+            ..default()
+        })
+        .insert(Name::new(object.name.clone()))
+        .id();
+
+    // Collision shapes are defined relative to the tile image,
+    // so they are scaled along with it.
+    let collision_root = commands
+        .spawn(TransformBundle::from(
+            Transform::from_translation((size / 2.0).extend(0.0))
+                .with_scale((size / image_size).extend(1.0)),
+        ))
+        .id();
+
+    if collision::spawn_tile_colliders(
+        commands,
+        collision_root,
+        &tile,
+        image_size,
+        TileOrientation::default(),
+    ) {
+        commands
+            .entity(object_entity)
+            .insert(RigidBody::Fixed)
+            .add_child(collision_root);
+    } else {
+        commands.entity(collision_root).despawn();
+    }
+
+    Some((object_entity, bottom_left.extend(z)))
+}
@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use bevy_rapier2d::prelude::RigidBody;
+use tiled::PropertyValue;
+
+use crate::systems::ysort;
+
+use super::{
+    chunks::TileRegion,
+    collision,
+    edit::{self, LayerEdits, MapTile},
+    sprite_tiles, FlattenedLayer, InsertTiledClass, TiledLayer, TiledMap, TiledTileId,
+};
+
+fn layer_z(flattened_layer: &FlattenedLayer) -> i32 {
+    match flattened_layer.properties.get("z") {
+        Some(PropertyValue::IntValue(depth)) => *depth,
+        _ => -1,
+    }
+}
+
+// Spawns a tile of a tilemap along with its colliders and class components.
+// `tile_pos` is the position on the map, `local_pos` the one within the tilemap.
+pub(crate) fn spawn_tile(
+    commands: &mut Commands,
+    tiled_map: &TiledMap,
+    flattened_layer: &FlattenedLayer,
+    map_tile: MapTile,
+    tilemap_entity: Entity,
+    tile_pos: TilePos,
+    local_pos: TilePos,
+) -> Option<Entity> {
+    let map = &tiled_map.map;
+    let tileset = map.tilesets().get(map_tile.tileset_index)?;
+    let tile = map_tile.tile(map)?;
+
+    let texture_index = match tiled_map.tilemap_textures.get(&map_tile.tileset_index)? {
+        TilemapTexture::Vector(_) => tiled_map
+            .tile_image_offsets
+            .get(&(map_tile.tileset_index, map_tile.tile_id))
+            .copied()
+            .unwrap_or_default(),
+        _ => map_tile.tile_id,
+    };
+
+    let tile_entity = commands
+        .spawn(TileBundle {
+            position: local_pos,
+            tilemap_id: TilemapId(tilemap_entity),
+            texture_index: TileTextureIndex(texture_index),
+            flip: TileFlip {
+                x: map_tile.flip_h,
+                y: map_tile.flip_v,
+                d: map_tile.flip_d,
+            },
+            color: TileColor(flattened_layer.color()),
+            ..Default::default()
+        })
+        .insert(TiledTileId(map_tile.tile_id))
+        .id();
+
+    if let Some(class) = &tile.tile_type {
+        commands.add(InsertTiledClass {
+            entity: tile_entity,
+            class: class.clone(),
+            properties: tile.properties.clone(),
+        });
+    }
+
+    if collision::spawn_tile_colliders(
+        commands,
+        tile_entity,
+        &tile,
+        Vec2::new(tileset.tile_width as f32, tileset.tile_height as f32),
+        map_tile.orientation(),
+    ) {
+        let grid_size = TilemapGridSize {
+            x: map.tile_width as f32,
+            y: map.tile_height as f32,
+        };
+        let tile_world_pos = flattened_layer.tilemap_transform(map, layer_z(flattened_layer) as f32)
+            * tile_pos.center_in_world(&grid_size, &TilemapType::Square).extend(0.0);
+
+        commands
+            .entity(tile_entity)
+            .insert(RigidBody::Fixed)
+            .insert(TransformBundle::from(Transform::from_translation(tile_world_pos)));
+    }
+
+    Some(tile_entity)
+}
+
+// Spawns the tiles of `tileset_index` within `region` of a tile layer.
+// The tilemaps only cover the region and are returned to be parented to the layer entity.
+pub(crate) fn spawn_tile_layer(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    tiled_map: &TiledMap,
+    map_entity: Entity,
+    flattened_layer: &FlattenedLayer,
+    layer_data: &tiled::FiniteTileLayer,
+    layer_edits: Option<&LayerEdits>,
+    tileset_index: usize,
+    region: TileRegion,
+) -> Vec<Entity> {
+    let map = &tiled_map.map;
+    let tileset = &map.tilesets()[tileset_index];
+    let layer = &flattened_layer.layer;
+
+    let z = layer_z(flattened_layer);
+
+    // Image collections without a tilemap texture are spawned as sprites.
+    let Some(tilemap_texture) = tiled_map.tilemap_textures.get(&tileset_index) else {
+        if tileset.image.is_some() {
+            log::warn!("Skipped creating layer with missing tilemap textures.");
+            return Vec::new();
+        }
+
+        return sprite_tiles::spawn_sprite_tiles(
+            commands,
+            asset_server,
+            map,
+            flattened_layer,
+            layer_data,
+            layer_edits,
+            tileset_index,
+            tileset,
+            z as f32,
+            region,
+        );
+    };
+
+    let tile_size = TilemapTileSize {
+        x: tileset.tile_width as f32,
+        y: tileset.tile_height as f32,
+    };
+
+    let tile_spacing = TilemapSpacing {
+        x: tileset.spacing as f32,
+        y: tileset.spacing as f32,
+    };
+
+    let grid_size = TilemapGridSize {
+        x: map.tile_width as f32,
+        y: map.tile_height as f32,
+    };
+
+    let map_type = TilemapType::Square;
+
+    let tilemap_center_transform = flattened_layer.tilemap_transform(map, z as f32);
+
+    // The tilemaps of a region start at its bottom-left tile.
+    let region_transform = tilemap_center_transform
+        * Transform::from_translation(
+            TilePos {
+                x: region.min.x,
+                y: region.min.y,
+            }
+            .center_in_world(&grid_size, &map_type)
+            .extend(0.0),
+        );
+    let region_size = TilemapSize {
+        x: region.size.x,
+        y: region.size.y,
+    };
+
+    // Tall layers are split into a tilemap per row,
+    // so each row is sorted against the characters on its own.
+    let y_sort_offset = flattened_layer.y_sort_offset();
+    let y_sorted = y_sort_offset.is_some();
+
+    let mut tilemaps: HashMap<u32, (Entity, TileStorage)> = HashMap::new();
+
+    for UVec2 { x, y } in region.positions() {
+        let Some(map_tile) = edit::layer_tile(map, layer_data, layer_edits, UVec2::new(x, y)) else {
+            continue;
+        };
+
+        // Tiles of other tilesets are in another tilemap.
+        if map_tile.tileset_index != tileset_index {
+            continue;
+        }
+
+        let row = if y_sorted { y } else { 0 };
+        let tilemap_entity = tilemaps
+            .entry(row)
+            .or_insert_with(|| (commands.spawn_empty().id(), TileStorage::empty(region_size)))
+            .0;
+
+        let local_pos = TilePos {
+            x: x - region.min.x,
+            y: y - region.min.y,
+        };
+        let Some(tile_entity) = spawn_tile(
+            commands,
+            tiled_map,
+            flattened_layer,
+            map_tile,
+            tilemap_entity,
+            TilePos { x, y },
+            local_pos,
+        ) else {
+            continue;
+        };
+
+        tilemaps.get_mut(&row).unwrap().1.set(&local_pos, tile_entity);
+    }
+
+    let mut tilemap_entities = Vec::new();
+    for (row, (tilemap_entity, tile_storage)) in tilemaps {
+        let mut transform = region_transform;
+        if y_sorted {
+            // Rows are sorted by their bottom edge.
+            let row_bottom = (tilemap_center_transform
+                * TilePos { x: 0, y: row }
+                    .center_in_world(&grid_size, &map_type)
+                    .extend(0.0))
+            .y - grid_size.y / 2.0;
+            transform.translation.z =
+                ysort::sorted_z(row_bottom + y_sort_offset.unwrap_or_default());
+        }
+
+        let tilemap_bundle = TilemapBundle {
+            grid_size,
+            size: region_size,
+            storage: tile_storage,
+            texture: tilemap_texture.clone(),
+            tile_size,
+            spacing: tile_spacing,
+            transform,
+            map_type,
+            // Tilemaps inherit the visibility of the layer entity.
+            ..Default::default()
+        };
+        commands
+            .entity(tilemap_entity)
+            .insert(tilemap_bundle)
+            .insert(TiledLayer {
+                map: map_entity,
+                name: layer.name.clone(),
+                tileset_index,
+                origin: TilePos {
+                    x: region.min.x,
+                    y: region.min.y,
+                },
+                row: y_sorted.then_some(row),
+            });
+
+        tilemap_entities.push(tilemap_entity);
+    }
+
+    tilemap_entities
+}
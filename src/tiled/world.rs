@@ -0,0 +1,166 @@
+use std::path::Path;
+
+use anyhow::Result;
+use bevy::{
+    asset::{AssetPath, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+};
+use bevy_ecs_tilemap::prelude::TileStorage;
+use serde::Deserialize;
+
+use super::{despawn_map_layers, TiledLayersStorage, TiledMap, TiledMapBundle};
+
+// A map of a world, placed by its top-left corner. Tiled's world coordinates
+// are in pixels with y pointing down.
+pub struct WorldMap {
+    pub map: Handle<TiledMap>,
+    pub position: Vec2,
+    pub size: Vec2,
+}
+
+/// Several maps placed next to each other, loaded from a Tiled `.world` file.
+#[derive(TypeUuid)]
+#[uuid = "6b1b4f0e-2c2a-4a57-9d0e-5c1d8f1e7a43"]
+pub struct TiledWorld {
+    pub maps: Vec<WorldMap>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct WorldFile {
+    #[serde(default)]
+    pub maps: Vec<WorldFileMap>,
+    #[serde(default)]
+    pub patterns: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct WorldFileMap {
+    pub file_name: String,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+pub(crate) fn parse_world(bytes: &[u8]) -> Result<WorldFile> {
+    serde_json::from_slice(bytes).map_err(|e| anyhow::anyhow!("Could not load world: {e}"))
+}
+
+// Maps are centered around their transform, while the world places them by their top-left corner.
+pub(crate) fn map_translation(world_translation: Vec3, position: Vec2, size: Vec2) -> Vec3 {
+    let center = position + size / 2.0;
+    world_translation + Vec3::new(center.x, -center.y, 0.0)
+}
+
+pub(crate) fn load_world(bytes: &[u8], load_context: &mut LoadContext) -> Result<()> {
+    let world_file = parse_world(bytes)?;
+
+    if !world_file.patterns.is_empty() {
+        log::warn!("World patterns are not supported. Only the listed maps are loaded.");
+    }
+
+    // Map paths are relative to the world file.
+    let world_dir = load_context.path().parent().unwrap_or_else(|| Path::new(""));
+
+    let mut dependencies = Vec::new();
+    let mut maps = Vec::new();
+    for world_map in world_file.maps {
+        let map_path = AssetPath::new(world_dir.join(&world_map.file_name), None);
+        let map: Handle<TiledMap> = load_context.get_handle(map_path.clone());
+        dependencies.push(map_path);
+
+        maps.push(WorldMap {
+            map,
+            position: Vec2::new(world_map.x, world_map.y),
+            size: Vec2::new(world_map.width, world_map.height),
+        });
+    }
+
+    log::info!("Loaded world: {}", load_context.path().display());
+
+    let loaded_asset = LoadedAsset::new(TiledWorld { maps });
+    load_context.set_default_asset(loaded_asset.with_dependencies(dependencies));
+
+    Ok(())
+}
+
+// The map entities spawned for a world.
+#[derive(Component, Default)]
+pub struct TiledWorldMaps {
+    pub maps: Vec<Entity>,
+}
+
+#[derive(Default, Bundle)]
+pub struct TiledWorldBundle {
+    pub world: Handle<TiledWorld>,
+    pub maps: TiledWorldMaps,
+    // The top-left corner of the world.
+    pub transform: Transform,
+    pub global_transform: GlobalTransform,
+}
+
+// Spawns a map entity for each map of a world. The maps themselves are
+// then spawned by `process_loaded_maps`, just like a standalone map.
+pub fn process_loaded_worlds(
+    mut commands: Commands,
+    mut world_events: EventReader<AssetEvent<TiledWorld>>,
+    worlds: Res<Assets<TiledWorld>>,
+    mut world_query: Query<(&Handle<TiledWorld>, &Transform, &mut TiledWorldMaps)>,
+    new_worlds: Query<&Handle<TiledWorld>, Added<Handle<TiledWorld>>>,
+    mut layer_storage_q: Query<&mut TiledLayersStorage>,
+    tile_storage_q: Query<&TileStorage>,
+    children_q: Query<&Children>,
+) {
+    let mut changed_worlds = Vec::<Handle<TiledWorld>>::default();
+    for event in world_events.iter() {
+        match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+                log::info!("World changed!");
+                changed_worlds.push(handle.clone());
+            }
+            AssetEvent::Removed { handle } => {
+                log::info!("World removed!");
+                changed_worlds.retain(|changed_handle| changed_handle == handle);
+            }
+        }
+    }
+
+    for new_world_handle in new_worlds.iter() {
+        changed_worlds.push(new_world_handle.clone_weak());
+    }
+
+    for changed_world in changed_worlds.iter() {
+        for (world_handle, world_transform, mut world_maps) in world_query.iter_mut() {
+            if world_handle != changed_world {
+                continue;
+            }
+
+            let Some(world) = worlds.get(world_handle) else {
+                continue;
+            };
+
+            for map_entity in world_maps.maps.drain(..) {
+                if let Ok(mut layer_storage) = layer_storage_q.get_mut(map_entity) {
+                    despawn_map_layers(&mut commands, &tile_storage_q, &children_q, &mut layer_storage);
+                }
+                commands.entity(map_entity).despawn_recursive();
+            }
+
+            for world_map in world.maps.iter() {
+                let translation = map_translation(world_transform.translation, world_map.position, world_map.size);
+
+                let map_entity = commands
+                    .spawn(TiledMapBundle {
+                        tiled_map: world_map.map.clone(),
+                        transform: Transform::from_translation(translation),
+                        ..Default::default()
+                    })
+                    .id();
+                world_maps.maps.push(map_entity);
+            }
+        }
+    }
+}
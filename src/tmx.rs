@@ -0,0 +1,424 @@
+// Reading TMX files. The tiled crate doesn't resolve object templates, so objects
+// created from a `.tx` template are filled in with the template's object before the
+// map is handed to it. Shared by the game's map loader and prototyp-check.
+
+use std::{
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+/// A start, end or empty element tag of an XML document.
+pub struct Tag<'a> {
+    pub name: &'a str,
+    pub attributes: Vec<(&'a str, String)>,
+    pub end: bool,
+    pub empty: bool,
+    // Where the tag is in the document, from its `<` to its `>`.
+    pub start: usize,
+    pub len: usize,
+}
+
+impl Tag<'_> {
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _)| *attribute == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn after(&self) -> usize {
+        self.start + self.len
+    }
+}
+
+pub fn unescape(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+pub fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The tags of an XML document in order. Comments, declarations and text are skipped.
+pub fn tags(xml: &str) -> Vec<Tag<'_>> {
+    let mut tags = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = xml[offset..].find('<') {
+        let start = offset + start;
+        let rest = &xml[start + 1..];
+        if let Some(comment) = rest.strip_prefix("!--") {
+            offset = comment
+                .find("-->")
+                .map_or(xml.len(), |end| start + 4 + end + 3);
+            continue;
+        }
+
+        let Some(end) = tag_end(rest) else {
+            break;
+        };
+        let tag = &rest[..end];
+        offset = start + 1 + end + 1;
+        if tag.starts_with('?') || tag.starts_with('!') {
+            continue;
+        }
+
+        let (tag, end) = match tag.strip_prefix('/') {
+            Some(tag) => (tag, true),
+            None => (tag, false),
+        };
+        let (tag, empty) = match tag.strip_suffix('/') {
+            Some(tag) => (tag, true),
+            None => (tag, false),
+        };
+
+        let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+        tags.push(Tag {
+            name: &tag[..name_end],
+            attributes: attributes(&tag[name_end..]),
+            end,
+            empty,
+            start,
+            len: offset - start,
+        });
+    }
+
+    tags
+}
+
+// The end of a tag, skipping over `>` within quoted attribute values.
+fn tag_end(tag: &str) -> Option<usize> {
+    let mut quote = None;
+    for (index, c) in tag.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '>') => return Some(index),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn attributes(mut rest: &str) -> Vec<(&str, String)> {
+    let mut attributes = Vec::new();
+    loop {
+        rest = rest.trim_start();
+        let Some(equals) = rest.find('=') else {
+            break;
+        };
+        let name = rest[..equals].trim();
+        let value = rest[equals + 1..].trim_start();
+        let Some(quote) = value.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+            break;
+        };
+        let Some(end) = value[1..].find(quote) else {
+            break;
+        };
+
+        attributes.push((name, unescape(&value[1..end + 1])));
+        rest = &value[end + 2..];
+    }
+
+    attributes
+}
+
+// An element directly within another one, as written in the document.
+struct Child<'a> {
+    name: &'a str,
+    xml: &'a str,
+    // The `name` attributes of the <property> elements of a <properties> child.
+    properties: Vec<(String, &'a str)>,
+}
+
+// The object element of a template: its attributes and children.
+struct TemplateObject<'a> {
+    attributes: Vec<(&'a str, String)>,
+    children: Vec<Child<'a>>,
+}
+
+// The children of the element whose start tag is `tags[index]`.
+fn children<'a>(xml: &'a str, tags: &[Tag<'a>], index: usize) -> Vec<Child<'a>> {
+    let mut children = Vec::new();
+    if tags[index].empty {
+        return children;
+    }
+
+    let mut depth = 0;
+    let mut child_start = 0;
+    let mut properties = Vec::new();
+    for (i, tag) in tags.iter().enumerate().skip(index + 1) {
+        if tag.end {
+            if depth == 0 {
+                break;
+            }
+            depth -= 1;
+            if depth == 0 {
+                let start = &tags[child_start];
+                children.push(Child {
+                    name: start.name,
+                    xml: &xml[start.start..tag.after()],
+                    properties: std::mem::take(&mut properties),
+                });
+            }
+            continue;
+        }
+
+        if depth == 0 {
+            child_start = i;
+        }
+        if depth == 1 && tag.name == "property" && tags[child_start].name == "properties" {
+            let element_end = if tag.empty {
+                tag.after()
+            } else {
+                element_end(tags, i).unwrap_or(tag.after())
+            };
+            if let Some(name) = tag.attribute("name") {
+                properties.push((name.to_string(), &xml[tag.start..element_end]));
+            }
+        }
+
+        if tag.empty {
+            if depth == 0 {
+                children.push(Child {
+                    name: tag.name,
+                    xml: &xml[tag.start..tag.after()],
+                    properties: Vec::new(),
+                });
+            }
+        } else {
+            depth += 1;
+        }
+    }
+
+    children
+}
+
+// The end of the element whose start tag is `tags[index]`, past its end tag.
+fn element_end(tags: &[Tag], index: usize) -> Option<usize> {
+    let mut depth = 0;
+    for tag in tags.iter().skip(index + 1) {
+        if tag.end {
+            if depth == 0 {
+                return Some(tag.after());
+            }
+            depth -= 1;
+        } else if !tag.empty {
+            depth += 1;
+        }
+    }
+
+    None
+}
+
+fn template_object<'a>(xml: &'a str, tags: &[Tag<'a>]) -> Option<TemplateObject<'a>> {
+    // The object is a direct child of <template>, after the template's <tileset>, if any.
+    let index = tags
+        .iter()
+        .position(|tag| tag.name == "object" && !tag.end)?;
+    Some(TemplateObject {
+        attributes: tags[index].attributes.clone(),
+        children: children(xml, tags, index),
+    })
+}
+
+// Shapes and text replace the rectangle an object is by default.
+const SHAPES: [&str; 5] = ["ellipse", "point", "polygon", "polyline", "text"];
+
+// The object instance `object` completed with `template`: attributes and properties
+// the instance doesn't set are taken from the template.
+fn instantiate(xml: &str, tags: &[Tag], index: usize, template: &TemplateObject) -> String {
+    let object = &tags[index];
+    let own = children(xml, tags, index);
+
+    let mut start_tag = format!("<{}", object.name);
+    for (name, value) in object.attributes.iter() {
+        if *name != "template" {
+            start_tag.push_str(&format!(r#" {name}="{}""#, escape(value)));
+        }
+    }
+    for (name, value) in template.attributes.iter() {
+        // Tile objects of templates refer to the template's own tilesets.
+        if *name == "gid" || *name == "id" || object.attribute(name).is_some() {
+            continue;
+        }
+        start_tag.push_str(&format!(r#" {name}="{}""#, escape(value)));
+    }
+    start_tag.push('>');
+
+    let own_properties = own.iter().find(|child| child.name == "properties");
+    let template_properties = template
+        .children
+        .iter()
+        .find(|child| child.name == "properties");
+    let mut properties: Vec<&str> = own_properties
+        .into_iter()
+        .flat_map(|child| child.properties.iter().map(|(_, xml)| *xml))
+        .collect();
+    for (name, xml) in template_properties
+        .into_iter()
+        .flat_map(|child| child.properties.iter())
+    {
+        if !own_properties.map_or(false, |own| {
+            own.properties.iter().any(|(own, _)| own == name)
+        }) {
+            properties.push(xml);
+        }
+    }
+
+    let mut instance = start_tag;
+    if !properties.is_empty() {
+        instance.push_str("<properties>");
+        for property in properties {
+            instance.push_str(property);
+        }
+        instance.push_str("</properties>");
+    }
+
+    let has_shape = own.iter().any(|child| SHAPES.contains(&child.name));
+    let template_shapes = template
+        .children
+        .iter()
+        .filter(|child| !has_shape && SHAPES.contains(&child.name));
+    for child in own
+        .iter()
+        .filter(|child| child.name != "properties")
+        .chain(template_shapes)
+    {
+        instance.push_str(child.xml);
+    }
+    instance.push_str(&format!("</{}>", object.name));
+
+    instance
+}
+
+/// `tmx` with the objects created from templates completed with their template,
+/// which is read relative to `map_path`.
+pub fn resolve_templates(tmx: &str, map_path: &Path) -> Result<String, String> {
+    let dir = map_path.parent().unwrap_or_else(|| Path::new(""));
+    let tags = tags(tmx);
+
+    let mut loaded: Vec<(PathBuf, String)> = Vec::new();
+    let mut resolved = String::with_capacity(tmx.len());
+    let mut copied = 0;
+    for (index, tag) in tags.iter().enumerate() {
+        let Some(template) = tag
+            .attribute("template")
+            .filter(|_| tag.name == "object" && !tag.end)
+        else {
+            continue;
+        };
+        // Skips objects nested in one already replaced.
+        if tag.start < copied {
+            continue;
+        }
+
+        let path = dir.join(template);
+        if !loaded.iter().any(|(loaded, _)| *loaded == path) {
+            let data = std::fs::read_to_string(&path)
+                .map_err(|e| format!("Could not read template {}: {e}", path.display()))?;
+            loaded.push((path.clone(), data));
+        }
+        let (_, data) = loaded.iter().find(|(loaded, _)| *loaded == path).unwrap();
+        let template_tags = self::tags(data);
+        let template = template_object(data, &template_tags)
+            .ok_or_else(|| format!("Template {} has no object", path.display()))?;
+
+        let end = if tag.empty {
+            tag.after()
+        } else {
+            element_end(&tags, index)
+                .ok_or_else(|| format!("An object from {} isn't closed", path.display()))?
+        };
+        resolved.push_str(&tmx[copied..tag.start]);
+        resolved.push_str(&instantiate(tmx, &tags, index, &template));
+        copied = end;
+    }
+    resolved.push_str(&tmx[copied..]);
+
+    Ok(resolved)
+}
+
+/// Loads the TMX map at `path` with its templates resolved.
+pub fn load_map(path: impl AsRef<Path>) -> Result<tiled::Map, String> {
+    let path = path.as_ref();
+    let tmx = std::fs::read_to_string(path)
+        .map_err(|e| format!("Could not read {}: {e}", path.display()))?;
+    load_map_from(&tmx, path)
+}
+
+/// Loads a TMX map from its contents `tmx`, with paths relative to `path`.
+pub fn load_map_from(tmx: &str, path: &Path) -> Result<tiled::Map, String> {
+    let tmx = resolve_templates(tmx, path)?;
+    tiled::Loader::new()
+        .load_tmx_map_from(BufReader::new(tmx.as_bytes()), path)
+        .map_err(|e| format!("Could not load TMX map: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn objects_get_their_template() {
+        let tmx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.9" orientation="orthogonal" renderorder="right-down" width="2" height="2" tilewidth="32" tileheight="32" infinite="0" nextlayerid="2" nextobjectid="4">
+ <objectgroup id="1" name="objects">
+  <object id="1" template="templates/npc.tx" x="1" y="2">
+   <properties>
+    <property name="id" value="patient"/>
+   </properties>
+  </object>
+  <object id="2" template="templates/sign.tx" x="3" y="4" width="10"/>
+  <object id="3" class="other" x="5" y="6"/>
+ </objectgroup>
+</map>"#;
+        let map = load_map_from(tmx, Path::new("assets/map/test.tmx")).unwrap();
+        let tiled::LayerType::ObjectLayer(layer) = map.get_layer(0).unwrap().layer_type() else {
+            panic!("Expected an object layer");
+        };
+        let objects: Vec<_> = layer.objects().collect();
+
+        assert_eq!(objects[0].user_type, "npc");
+        assert_eq!((objects[0].x, objects[0].y), (1.0, 2.0));
+        assert_eq!(
+            objects[0].properties.get("id"),
+            Some(&tiled::PropertyValue::StringValue("patient".to_string()))
+        );
+        assert_eq!(
+            objects[0].properties.get("z"),
+            Some(&tiled::PropertyValue::IntValue(10))
+        );
+
+        assert_eq!(objects[1].user_type, "sign");
+        assert_eq!(
+            objects[1].shape,
+            tiled::ObjectShape::Rect {
+                width: 10.0,
+                height: 18.0
+            }
+        );
+        assert_eq!(
+            objects[1].properties.get("id"),
+            Some(&tiled::PropertyValue::IntValue(0))
+        );
+
+        assert_eq!(objects[2].user_type, "other");
+        assert!(objects[2].properties.is_empty());
+    }
+
+    #[test]
+    fn missing_templates_are_errors() {
+        let tmx = r#"<map><objectgroup><object id="1" template="missing.tx"/></objectgroup></map>"#;
+        assert!(resolve_templates(tmx, Path::new("assets/map/test.tmx")).is_err());
+    }
+}